@@ -0,0 +1,230 @@
+use crate::card::BackSide;
+use crate::common::{timestamp_as_years, CardId};
+use crate::reviews::Recall;
+use crate::Card;
+use timestamped::TimeStamp;
+
+/// Separates alternative accepted answers in a text backside, e.g. `car | automobile`.
+pub const ALIAS_SEPARATOR: char = '|';
+
+/// How forgiving the answer checking should be.
+#[derive(Debug, Clone)]
+pub struct Tolerance {
+    /// Max edit distance as a fraction of the expected answer's length that still counts as a typo.
+    pub typo_ratio: f32,
+    /// Below this similarity the answer is considered wrong, above it you at least recognized it.
+    pub late_similarity: f32,
+    /// How many years a typed date can be off and still count as somewhat right.
+    pub year_tolerance: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            typo_ratio: 0.2,
+            late_similarity: 0.5,
+            year_tolerance: 1.,
+        }
+    }
+}
+
+/// Result of comparing a typed answer with the backside of a card.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnswerCheck {
+    /// From 0.0 (nothing in common) to 1.0 (identical after normalization).
+    pub similarity: f32,
+    /// The accepted answer that came closest to what was typed.
+    pub closest: Option<String>,
+    /// Suggested grade, clients may let the user override it.
+    pub grade: Recall,
+}
+
+impl AnswerCheck {
+    fn wrong() -> Self {
+        Self {
+            similarity: 0.,
+            closest: None,
+            grade: Recall::None,
+        }
+    }
+
+    pub fn is_correct(&self) -> bool {
+        matches!(self.grade, Recall::Some | Recall::Perfect)
+    }
+}
+
+pub fn check_answer(answer: &str, back: &BackSide) -> AnswerCheck {
+    check_answer_with(answer, back, &Tolerance::default())
+}
+
+pub fn check_answer_with(answer: &str, back: &BackSide, tolerance: &Tolerance) -> AnswerCheck {
+    match back {
+        BackSide::Text(text) => check_text(answer, &aliases(text), tolerance),
+        BackSide::Card(id) => check_text(answer, &card_names(*id), tolerance),
+        BackSide::List(ids) => check_list(answer, ids, tolerance),
+        BackSide::Time(time) => check_time(answer, time, tolerance),
+    }
+}
+
+fn aliases(text: &str) -> Vec<String> {
+    text.split(ALIAS_SEPARATOR)
+        .map(|alias| alias.trim().to_string())
+        .filter(|alias| !alias.is_empty())
+        .collect()
+}
+
+fn card_names(id: CardId) -> Vec<String> {
+    match Card::from_id(id) {
+        Some(card) => aliases(&card.print()),
+        None => vec![],
+    }
+}
+
+fn check_text(answer: &str, expected: &[String], tolerance: &Tolerance) -> AnswerCheck {
+    let answer = normalize(answer);
+    let mut best = AnswerCheck::wrong();
+
+    for candidate in expected {
+        let normalized = normalize(candidate);
+        let distance = levenshtein(&answer, &normalized);
+        let max_len = answer.chars().count().max(normalized.chars().count());
+        let similarity = if max_len == 0 {
+            1.
+        } else {
+            1. - distance as f32 / max_len as f32
+        };
+
+        if best.closest.is_some() && similarity <= best.similarity {
+            continue;
+        }
+
+        let typo_limit = (normalized.chars().count() as f32 * tolerance.typo_ratio) as usize;
+        let grade = if distance == 0 {
+            Recall::Perfect
+        } else if distance <= typo_limit {
+            Recall::Some
+        } else if similarity >= tolerance.late_similarity {
+            Recall::Late
+        } else {
+            Recall::None
+        };
+
+        best = AnswerCheck {
+            similarity,
+            closest: Some(candidate.clone()),
+            grade,
+        };
+    }
+
+    best
+}
+
+/// Every card in the list must be named, in any order, separated by commas.
+fn check_list(answer: &str, ids: &[CardId], tolerance: &Tolerance) -> AnswerCheck {
+    if ids.is_empty() {
+        return check_text(answer, &[], tolerance);
+    }
+
+    let mut typed: Vec<&str> = answer
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    let mut similarity = 0.;
+    let mut grade = Recall::Perfect;
+    let mut closest = vec![];
+
+    for id in ids {
+        let names = card_names(*id);
+        let best = typed
+            .iter()
+            .enumerate()
+            .map(|(idx, part)| (idx, check_text(part, &names, tolerance)))
+            .max_by(|a, b| a.1.similarity.total_cmp(&b.1.similarity));
+
+        match best {
+            Some((idx, check)) => {
+                typed.remove(idx);
+                similarity += check.similarity;
+                grade = grade.min(check.grade);
+                closest.extend(check.closest);
+            }
+            None => grade = Recall::None,
+        }
+    }
+
+    AnswerCheck {
+        similarity: similarity / ids.len() as f32,
+        closest: Some(closest.join(", ")),
+        grade,
+    }
+}
+
+fn check_time(answer: &str, expected: &TimeStamp, tolerance: &Tolerance) -> AnswerCheck {
+    let Some(typed) = TimeStamp::from_string(answer.trim().to_string()) else {
+        return check_text(answer, &[expected.serialize()], tolerance);
+    };
+
+    if &typed == expected {
+        return AnswerCheck {
+            similarity: 1.,
+            closest: Some(expected.serialize()),
+            grade: Recall::Perfect,
+        };
+    }
+
+    let (Some(typed), Some(expected_years)) =
+        (timestamp_as_years(&typed), timestamp_as_years(expected))
+    else {
+        return check_text(answer, &[expected.serialize()], tolerance);
+    };
+
+    let off_by = (typed - expected_years).abs();
+    let grade = if off_by <= tolerance.year_tolerance {
+        Recall::Some
+    } else if off_by <= tolerance.year_tolerance * 10. {
+        Recall::Late
+    } else {
+        Recall::None
+    };
+
+    AnswerCheck {
+        similarity: 1. / (1. + off_by),
+        closest: Some(expected.serialize()),
+        grade,
+    }
+}
+
+/// Lowercases, strips punctuation and leading articles, and collapses whitespace.
+pub fn normalize(s: &str) -> String {
+    let s: String = s
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+
+    let mut words: Vec<&str> = s.split_whitespace().collect();
+    if words.len() > 1 && matches!(words[0], "the" | "a" | "an") {
+        words.remove(0);
+    }
+
+    words.join(" ")
+}
+
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j + 1] + 1).min(curr[j] + 1).min(prev[j] + cost);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}
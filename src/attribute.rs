@@ -1,11 +1,21 @@
-use crate::paths::get_attributes_path;
+use crate::card::{
+    AnyType, AttributeCard, BackConstraint, BackSide, CardCharacteristic, Conversion,
+};
+use crate::collections::{get_dirs, Collection};
+use crate::hierarchy::ClassHierarchy;
+use crate::paths::{get_attributes_path, get_collection_attributes_path};
 use crate::Card;
-use crate::{common::CardId, get_containing_file_paths, my_sanitize_filename};
-use eyre::Result;
+use crate::{common::CardId, get_containing_file_paths};
+use eyre::{bail, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::{Duration, Instant, SystemTime};
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Ord, Eq, PartialEq, PartialOrd, Copy, Hash)]
@@ -57,14 +67,145 @@ pub struct Attribute {
     pub dependencies: BTreeSet<CardId>,
 }
 
+/// A placeholder in an attribute pattern, filled in for each instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    /// `{}` or `{instance}`, the name of the instance.
+    Instance,
+    /// `{class}`, the name of the instance's class.
+    Class,
+    /// `{attr:<id>}`, the answer to another attribute of the same instance.
+    Attribute(AttributeId),
+}
+
+impl Placeholder {
+    fn parse(inner: &str) -> Option<Self> {
+        match inner.trim() {
+            "" | "instance" => Some(Self::Instance),
+            "class" => Some(Self::Class),
+            other => {
+                let id = other.strip_prefix("attr:")?.trim();
+                Some(Self::Attribute(AttributeId(Uuid::from_str(id).ok()?)))
+            }
+        }
+    }
+
+    fn resolve(&self, instance: CardId) -> String {
+        match self {
+            Placeholder::Instance => Card::from_id(instance)
+                .map(|card| card.print())
+                .unwrap_or_else(|| instance.to_string()),
+            Placeholder::Class => Card::from_id(instance)
+                .and_then(|card| card.class())
+                .and_then(Card::from_id)
+                .map(|class| class.print())
+                .unwrap_or_else(|| "?".to_string()),
+            Placeholder::Attribute(attribute) => {
+                let key = (*attribute, instance);
+                // Answers can refer to cards whose names have placeholders that lead back here.
+                if !RESOLVING.with(|resolving| resolving.borrow_mut().insert(key)) {
+                    return "?".to_string();
+                }
+
+                let value = Card::find_attribute_card(*attribute, instance)
+                    .and_then(Card::from_id)
+                    .and_then(|card| card.back_side().map(|back| back.to_string()))
+                    .unwrap_or_else(|| "?".to_string());
+
+                RESOLVING.with(|resolving| resolving.borrow_mut().remove(&key));
+                value
+            }
+        }
+    }
+}
+
+thread_local! {
+    /// The `{attr:}` placeholders being filled in on this thread, to stop on placeholders that refer to each other.
+    static RESOLVING: RefCell<BTreeSet<(AttributeId, CardId)>> = RefCell::new(BTreeSet::new());
+}
+
+/// The placeholders in the pattern along with the text they're written as, e.g. `("{class}", Placeholder::Class)`.
+/// Braces that don't contain a known placeholder are left as they are.
+pub fn parse_pattern(pattern: &str) -> Vec<(String, Placeholder)> {
+    let mut placeholders = vec![];
+    let mut rest = pattern;
+
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+
+        if let Some(placeholder) = Placeholder::parse(&rest[1..end]) {
+            placeholders.push((rest[..=end].to_string(), placeholder));
+        }
+        rest = &rest[end + 1..];
+    }
+
+    placeholders
+}
+
 impl Attribute {
+    /// The question for the given instance, patterns without placeholders are prefixed to the instance name.
     pub fn name(&self, card: CardId) -> String {
-        let card_text = Card::from_id(card).unwrap().print();
-        if self.pattern.contains("{}") {
-            self.pattern.replace("{}", &card_text)
-        } else {
-            format!("{}: {}", &self.pattern, card_text)
+        let placeholders = parse_pattern(&self.pattern);
+        if placeholders.is_empty() {
+            let card_text = Placeholder::Instance.resolve(card);
+            return format!("{}: {}", &self.pattern, card_text);
         }
+
+        let mut name = self.pattern.clone();
+        for (text, placeholder) in placeholders {
+            let value = match placeholder {
+                // Don't look up our own answer, it'd give away the answer or recurse forever.
+                Placeholder::Attribute(id) if id == self.id => "?".to_string(),
+                placeholder => placeholder.resolve(card),
+            };
+            name = name.replace(&text, &value);
+        }
+        name
+    }
+
+    /// Checks that the answer is of the attribute's back type, if it has one.
+    ///
+    /// An empty text answer is always accepted, it means the card hasn't been answered yet.
+    pub fn validate_back(&self, back: &BackSide) -> Result<()> {
+        let Some(back_type) = self.back_type else {
+            return Ok(());
+        };
+
+        if matches!(back, BackSide::Text(s) if s.is_empty()) {
+            return Ok(());
+        }
+
+        for id in back.dependencies() {
+            if Card::from_id(id).is_none() {
+                bail!("answer refers to missing card: {}", id);
+            }
+        }
+
+        let constraint = match back {
+            BackSide::List(ids) => BackConstraint::List(
+                ids.iter()
+                    .map(|_| CardCharacteristic::SubclassOf(back_type))
+                    .collect(),
+            ),
+            _ => BackConstraint::Card(CardCharacteristic::SubclassOf(back_type)),
+        };
+
+        if !back.matches_constraint(constraint) {
+            let class = Card::from_id(back_type)
+                .map(|card| card.print())
+                .unwrap_or_else(|| back_type.to_string());
+            bail!(
+                "answer to '{}' must be an instance of '{}', got: {}",
+                self.pattern,
+                class,
+                back
+            );
+        }
+
+        Ok(())
     }
 
     pub fn pattern(&self) -> &str {
@@ -72,23 +213,164 @@ impl Attribute {
     }
 
     pub fn load_all() -> Vec<Self> {
-        get_containing_file_paths(&get_attributes_path(), None)
-            .into_iter()
-            .map(|path| std::fs::read_to_string(path).unwrap())
-            .map(|s| toml::from_str(&s).unwrap())
-            .collect()
+        with_index(|index| {
+            index
+                .attributes
+                .values()
+                .map(|(attr, _)| attr.clone())
+                .collect()
+        })
     }
 
+    /// Saves the attribute where it's already stored, new attributes go in the personal directory.
     pub fn save(&self) -> Result<()> {
-        let filename = my_sanitize_filename(&self.pattern);
-        let path = get_attributes_path().join(filename);
+        let path = with_index(|index| index.attributes.get(&self.id).map(|(_, path)| path.clone()))
+            .unwrap_or_else(|| attribute_path(&get_attributes_path(), self.id));
+        self.save_at(path)
+    }
+
+    /// Saves the attribute in the collection, or the personal directory if `None`,
+    /// moving it there if it was stored somewhere else.
+    pub fn save_in(&self, collection: Option<&Collection>) -> Result<()> {
+        let dir = match collection {
+            Some(col) => collection_attributes_path(col),
+            None => get_attributes_path(),
+        };
+
+        let old_path =
+            with_index(|index| index.attributes.get(&self.id).map(|(_, path)| path.clone()));
+        let new_path = attribute_path(&dir, self.id);
+        self.save_at(new_path.clone())?;
+
+        if let Some(old_path) = old_path.filter(|old_path| old_path != &new_path) {
+            fs::remove_file(old_path)?;
+        }
+        Ok(())
+    }
+
+    fn save_at(&self, path: PathBuf) -> Result<()> {
         let mut f = fs::File::create(&path)?;
         let s = toml::to_string_pretty(self)?;
         f.write_all(&mut s.as_bytes())?;
 
+        update_index(|index| {
+            index.attributes.insert(self.id, (self.clone(), path));
+        });
+
         Ok(())
     }
 
+    /// Changes the pattern, attribute cards are persisted again so their files match the new question.
+    pub fn rename(id: AttributeId, pattern: String) -> Result<()> {
+        let Some(mut attr) = Self::load(id) else {
+            bail!("no attribute with id {}", id.into_inner());
+        };
+        attr.pattern = pattern;
+        attr.save()?;
+
+        for card in attr.cards() {
+            if let Some(mut card) = Card::from_id(card) {
+                card.persist();
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves changes to an existing attribute, failing if its attribute cards would no longer be valid.
+    pub fn update(&self) -> Result<()> {
+        if Self::load(self.id).is_none() {
+            bail!("no attribute with id {}", self.id.into_inner());
+        }
+
+        match Card::from_id(self.class) {
+            Some(card) if card.is_class() => {}
+            _ => bail!("attribute class {} is not a class card", self.class),
+        }
+
+        for id in self.cards() {
+            let card = Card::from_id(id).unwrap();
+            if let Some(back) = card.back_side() {
+                self.validate_back(back)?;
+            }
+        }
+
+        self.save()
+    }
+
+    /// Deletes the attribute, its attribute cards are turned into normal cards so they keep their reviews.
+    /// Returns the converted cards.
+    pub fn delete(id: AttributeId) -> Result<Vec<CardId>> {
+        let Some(path) =
+            with_index(|index| index.attributes.get(&id).map(|(_, path)| path.clone()))
+        else {
+            bail!("no attribute with id {}", id.into_inner());
+        };
+
+        let attr = Self::load(id).unwrap();
+        let mut converted = vec![];
+        for card in attr.cards() {
+            let card = Card::from_id(card).unwrap();
+            let card = card.convert(Conversion::Normal { back: None })?;
+            converted.push(card.id());
+        }
+
+        fs::remove_file(path)?;
+        update_index(|index| {
+            index.attributes.remove(&id);
+        });
+
+        Ok(converted)
+    }
+
+    /// Moves the attribute file to `path` and leaves its attribute cards as they are,
+    /// for when they're moved out along with it. Returns where the file was stored.
+    pub(crate) fn move_out(id: AttributeId, path: &Path) -> Result<PathBuf> {
+        let Some(old_path) =
+            with_index(|index| index.attributes.get(&id).map(|(_, path)| path.clone()))
+        else {
+            bail!("no attribute with id {}", id.into_inner());
+        };
+
+        fs::rename(&old_path, path)?;
+        update_index(|index| {
+            index.attributes.remove(&id);
+        });
+        Ok(old_path)
+    }
+
+    /// Moves an attribute file that was moved out with [`Attribute::move_out`] back to where it was stored.
+    pub(crate) fn move_back(path: &Path, original_path: &Path) -> Result<()> {
+        let attr: Attribute = toml::from_str(&fs::read_to_string(path)?)?;
+        if Self::load(attr.id).is_some() {
+            bail!(
+                "an attribute with id {} already exists",
+                attr.id.into_inner()
+            );
+        }
+
+        if let Some(parent) = original_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(path, original_path)?;
+        update_index(|index| {
+            index
+                .attributes
+                .insert(attr.id, (attr, original_path.to_path_buf()));
+        });
+        Ok(())
+    }
+
+    /// The attribute cards made from this attribute.
+    pub fn cards(&self) -> Vec<CardId> {
+        Card::load_all_cards()
+            .into_iter()
+            .filter(|card| {
+                matches!(card.card_type(), AnyType::Attribute(attr) if attr.attribute == self.id)
+            })
+            .map(|card| card.id())
+            .collect()
+    }
+
     pub fn load_from_class_only(class: CardId) -> Vec<Self> {
         let mut attrs = Self::load_all();
         attrs.retain(|attr| attr.class == class);
@@ -109,9 +391,7 @@ impl Attribute {
     }
 
     pub fn load(id: AttributeId) -> Option<Self> {
-        Self::load_all()
-            .into_iter()
-            .find(|concept| concept.id == id)
+        with_index(|index| index.attributes.get(&id).map(|(attr, _)| attr.clone()))
     }
 
     pub fn create(pattern: String, concept: CardId, back_type: Option<CardId>) -> AttributeId {
@@ -127,3 +407,241 @@ impl Attribute {
         attr.id
     }
 }
+
+/// Attribute cards created by [`generate_missing_cards`].
+#[derive(Debug, Clone, Default)]
+pub struct GenerationReport {
+    /// The new cards, along with the attribute and instance they were made for.
+    pub created: Vec<GeneratedCard>,
+    /// How many instances already had a card for the attribute.
+    pub existing: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratedCard {
+    pub attribute: AttributeId,
+    pub instance: CardId,
+    pub card: CardId,
+}
+
+/// Creates an attribute card with an empty answer for every instance the attribute applies to
+/// that doesn't have one yet. The cards are put in the same category as their instance.
+pub fn generate_missing_cards(attribute: AttributeId) -> Result<GenerationReport> {
+    let Some(attribute) = Attribute::load(attribute) else {
+        bail!("no attribute with id {}", attribute.into_inner());
+    };
+
+    generate(&[attribute], None)
+}
+
+/// Same as [`generate_missing_cards`] for every attribute that applies to instances of the class,
+/// including the ones defined on its parent classes.
+pub fn generate_missing_cards_for_class(class: CardId) -> Result<GenerationReport> {
+    let Some(class_card) = Card::from_id(class) else {
+        bail!("no card with id {}", class);
+    };
+    if !class_card.is_class() {
+        bail!("'{}' is not a class", class_card.print());
+    }
+
+    let mut classes = class_card.load_belonging_classes();
+    classes.push(class);
+
+    let attributes: Vec<Attribute> = Attribute::load_all()
+        .into_iter()
+        .filter(|attr| classes.contains(&attr.class))
+        .collect();
+
+    generate(&attributes, Some(class))
+}
+
+/// If `within` is set, only instances belonging to that class are considered.
+fn generate(attributes: &[Attribute], within: Option<CardId>) -> Result<GenerationReport> {
+    let cards = Card::load_all_cards();
+    let mut report = GenerationReport::default();
+
+    let existing: BTreeSet<(AttributeId, CardId)> = cards
+        .iter()
+        .filter_map(|card| match card.card_type() {
+            AnyType::Attribute(attr) => Some((attr.attribute, attr.instance)),
+            _ => None,
+        })
+        .collect();
+
+    let hierarchy = ClassHierarchy::from_cards(&cards);
+
+    for attribute in attributes {
+        for instance in cards.iter().filter(|card| card.is_instance()) {
+            if !hierarchy.is_within(instance.id(), attribute.class)
+                || within.is_some_and(|class| !hierarchy.is_within(instance.id(), class))
+            {
+                continue;
+            }
+
+            if existing.contains(&(attribute.id, instance.id())) {
+                report.existing += 1;
+                continue;
+            }
+
+            let data = AttributeCard {
+                attribute: attribute.id,
+                back: BackSide::default(),
+                instance: instance.id(),
+            };
+            let card = Card::new_attribute(data, instance.category())?;
+
+            report.created.push(GeneratedCard {
+                attribute: attribute.id,
+                instance: instance.id(),
+                card: card.id(),
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+/// All attributes by id, along with the file they're stored in.
+struct AttributeIndex {
+    attributes: BTreeMap<AttributeId, (Attribute, PathBuf)>,
+    /// Files that couldn't be read as attributes, with why.
+    invalid: Vec<(PathBuf, String)>,
+    /// Modification time and size of each attribute file when the index was last updated,
+    /// if a file is added, removed or edited behind our back the index is rebuilt.
+    files: BTreeMap<PathBuf, FileStamp>,
+    /// When the files were last compared to the index.
+    checked: Instant,
+}
+
+type FileStamp = (Option<SystemTime>, u64);
+
+/// How long the index is trusted before the files are compared to it again,
+/// so lookups in a loop don't go through the file system each time.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+static INDEX: RwLock<Option<AttributeIndex>> = RwLock::new(None);
+
+impl AttributeIndex {
+    fn build(files: BTreeMap<PathBuf, FileStamp>) -> Self {
+        let mut attributes = BTreeMap::new();
+        let mut invalid = vec![];
+
+        for path in files.keys() {
+            let attr = fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|s| toml::from_str::<Attribute>(&s).map_err(|e| e.to_string()));
+
+            match attr {
+                Ok(attr) => {
+                    attributes.insert(attr.id, (attr, path.clone()));
+                }
+                Err(error) => invalid.push((path.clone(), error)),
+            }
+        }
+
+        Self {
+            attributes,
+            invalid,
+            files,
+            checked: Instant::now(),
+        }
+    }
+}
+
+fn with_index<T>(f: impl FnOnce(&AttributeIndex) -> T) -> T {
+    {
+        let guard = INDEX.read().unwrap();
+        if let Some(index) = guard.as_ref() {
+            if index.checked.elapsed() < RECHECK_INTERVAL {
+                return f(index);
+            }
+        }
+    }
+
+    let mut guard = INDEX.write().unwrap();
+    let files = file_stamps();
+    match guard.as_mut() {
+        Some(index) if index.files == files => index.checked = Instant::now(),
+        _ => *guard = Some(AttributeIndex::build(files)),
+    }
+    f(guard.as_ref().unwrap())
+}
+
+fn update_index(f: impl FnOnce(&mut AttributeIndex)) {
+    with_index(|_| ());
+    let mut guard = INDEX.write().unwrap();
+    let index = guard.get_or_insert_with(|| AttributeIndex::build(file_stamps()));
+    f(index);
+    index.files = file_stamps();
+    index.checked = Instant::now();
+}
+
+/// Forces the attributes to be read from disk again, for when files were edited in place
+/// and the change should be seen right away.
+pub fn invalidate_cache() {
+    *INDEX.write().unwrap() = None;
+    Card::invalidate_attribute_cards();
+}
+
+/// Attribute files that couldn't be read, with why. They're left out of the attributes.
+pub fn invalid_files() -> Vec<(PathBuf, String)> {
+    with_index(|index| index.invalid.clone())
+}
+
+/// Renames attribute files to their id, attributes used to be named after their pattern.
+/// Returns how many files were renamed.
+pub fn migrate_file_names() -> Result<usize> {
+    let renames: Vec<(PathBuf, PathBuf)> = with_index(|index| {
+        index
+            .attributes
+            .values()
+            .filter_map(|(attr, path)| {
+                let id_path = attribute_path(path.parent()?, attr.id);
+                (path != &id_path).then(|| (path.clone(), id_path))
+            })
+            .collect()
+    });
+
+    for (from, to) in &renames {
+        if to.exists() {
+            bail!(
+                "can't rename {} to {}, the file already exists",
+                from.display(),
+                to.display()
+            );
+        }
+        fs::rename(from, to)?;
+    }
+
+    invalidate_cache();
+    Ok(renames.len())
+}
+
+fn attribute_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![get_attributes_path()];
+    dirs.extend(get_dirs(&get_collection_attributes_path()));
+    dirs
+}
+
+fn file_stamps() -> BTreeMap<PathBuf, FileStamp> {
+    attribute_dirs()
+        .iter()
+        .flat_map(|dir| get_containing_file_paths(dir, None))
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            Some((path, (metadata.modified().ok(), metadata.len())))
+        })
+        .collect()
+}
+
+fn attribute_path(dir: &Path, id: AttributeId) -> PathBuf {
+    let mut path = dir.join(id.into_inner().to_string());
+    path.set_extension("toml");
+    path
+}
+
+pub fn collection_attributes_path(collection: &Collection) -> PathBuf {
+    let path = get_collection_attributes_path().join(collection.name());
+    fs::create_dir_all(&path).unwrap();
+    path
+}
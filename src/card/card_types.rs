@@ -103,14 +103,12 @@ pub struct UnfinishedCard {
 }
 
 impl InstanceCard {
+    /// Whether the class of this instance describes events.
+    /// Returns false if the class is missing or not a class card.
     pub fn is_event(&self) -> bool {
-        if let AnyType::Class(class) = Card::from_id(self.class).unwrap().data {
-            return class.is_event;
-        } else {
-            panic!(
-                "card {} has class id: {} which is not a card",
-                self.name, self.class
-            );
+        match Card::from_id(self.class).map(|card| card.data) {
+            Some(AnyType::Class(class)) => class.is_event,
+            _ => false,
         }
     }
 }
@@ -141,6 +139,16 @@ pub struct AttributeCard {
     pub instance: CardId,
 }
 
+impl AttributeCard {
+    /// Checks that the attribute exists and that the answer matches its back type.
+    pub fn validate(&self) -> eyre::Result<()> {
+        match Attribute::load(self.attribute) {
+            Some(attribute) => attribute.validate_back(&self.back),
+            None => eyre::bail!("no attribute with id {}", self.attribute.into_inner()),
+        }
+    }
+}
+
 /// A specific instance of a class
 /// For example, the instance might be Elvis Presley where the concept would be "Person"
 /// the right answer is to know which class the instance belongs to
@@ -180,6 +188,7 @@ impl CardTrait for StatementCard {
     }
 }
 
+/// Something that happened at a point in time, or over a period if it has an end time.
 #[derive(Debug, Clone)]
 pub struct EventCard {
     pub front: String,
@@ -187,6 +196,26 @@ pub struct EventCard {
     pub end_time: Option<TimeStamp>,
 }
 
+impl EventCard {
+    /// When the event ended, events without an end time are treated as instantaneous.
+    pub fn end(&self) -> &TimeStamp {
+        self.end_time.as_ref().unwrap_or(&self.start_time)
+    }
+
+    pub fn overlaps(&self, other: &EventCard) -> bool {
+        self.start_time <= *other.end() && other.start_time <= *self.end()
+    }
+
+    /// Whether the other event happened entirely within this one.
+    pub fn contains(&self, other: &EventCard) -> bool {
+        self.start_time <= other.start_time && other.end() <= self.end()
+    }
+
+    pub fn contains_time(&self, time: &TimeStamp) -> bool {
+        &self.start_time <= time && time <= self.end()
+    }
+}
+
 impl From<EventCard> for AnyType {
     fn from(value: EventCard) -> Self {
         Self::Event(value)
@@ -0,0 +1,279 @@
+use eyre::{bail, Result};
+use timestamped::TimeStamp;
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CardType {
+    Instance,
+    Normal,
+    Unfinished,
+    Attribute,
+    Class,
+    Statement,
+    Event,
+}
+
+impl CardType {
+    pub const ALL: [CardType; 7] = [
+        CardType::Instance,
+        CardType::Normal,
+        CardType::Unfinished,
+        CardType::Attribute,
+        CardType::Class,
+        CardType::Statement,
+        CardType::Event,
+    ];
+
+    pub fn of(ty: &AnyType) -> Self {
+        match ty {
+            AnyType::Instance(_) => Self::Instance,
+            AnyType::Normal(_) => Self::Normal,
+            AnyType::Unfinished(_) => Self::Unfinished,
+            AnyType::Attribute(_) => Self::Attribute,
+            AnyType::Class(_) => Self::Class,
+            AnyType::Statement(_) => Self::Statement,
+            AnyType::Event(_) => Self::Event,
+        }
+    }
+
+    /// Same as [`AnyType::type_name`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            CardType::Instance => "instance",
+            CardType::Normal => "normal",
+            CardType::Unfinished => "unfinished",
+            CardType::Attribute => "attribute",
+            CardType::Class => "class",
+            CardType::Statement => "statement",
+            CardType::Event => "event",
+        }
+    }
+}
+
+/// Target of a card conversion along with the data the new type needs that the old one might not have.
+///
+/// Fields that are `None` are taken from the card being converted, see [`Card::set_parent_class`]
+/// to remove the parent of a class.
+#[derive(Debug, Clone)]
+pub enum Conversion {
+    Normal {
+        back: Option<BackSide>,
+    },
+    Unfinished,
+    Class {
+        parent_class: Option<CardId>,
+        back: Option<BackSide>,
+    },
+    Instance {
+        class: CardId,
+    },
+    Attribute {
+        attribute: AttributeId,
+        instance: CardId,
+        back: Option<BackSide>,
+    },
+    Statement,
+    Event {
+        start_time: TimeStamp,
+        end_time: Option<TimeStamp>,
+    },
+}
+
+impl Conversion {
+    pub fn card_type(&self) -> CardType {
+        match self {
+            Conversion::Normal { .. } => CardType::Normal,
+            Conversion::Unfinished => CardType::Unfinished,
+            Conversion::Class { .. } => CardType::Class,
+            Conversion::Instance { .. } => CardType::Instance,
+            Conversion::Attribute { .. } => CardType::Attribute,
+            Conversion::Statement => CardType::Statement,
+            Conversion::Event { .. } => CardType::Event,
+        }
+    }
+}
+
+impl Card<AnyType> {
+    pub fn card_kind(&self) -> CardType {
+        CardType::of(&self.data)
+    }
+
+    /// The types this card can be converted into.
+    pub fn valid_conversions(&self) -> Vec<CardType> {
+        CardType::ALL
+            .into_iter()
+            .filter(|ty| self.conversion_blocker(*ty).is_none())
+            .collect()
+    }
+
+    /// Explains why this card can't be converted into the given type, if it can't.
+    ///
+    /// Instances can be converted into instances of another class, and classes into classes
+    /// with another parent, those are checked when converting.
+    pub fn conversion_blocker(&self, target: CardType) -> Option<String> {
+        if target == self.card_kind() {
+            return match target {
+                CardType::Instance | CardType::Class => None,
+                _ => Some(format!("card is already of type {}", target.name())),
+            };
+        }
+
+        if self.is_class() {
+            let id = self.id();
+            let members: Vec<String> = Card::load_all_cards()
+                .into_iter()
+                .filter(|card| card.class() == Some(id))
+                .map(|card| card.print())
+                .collect();
+
+            if !members.is_empty() {
+                return Some(format!(
+                    "class '{}' still has instances or subclasses: {}",
+                    self.print(),
+                    members.join(", ")
+                ));
+            }
+
+            if !Attribute::load_from_class_only(id).is_empty() {
+                return Some(format!("class '{}' still has attributes", self.print()));
+            }
+        }
+
+        None
+    }
+
+    /// Converts the card into another type, keeping its id, reviews, dependencies and tags.
+    pub fn convert(self, conversion: Conversion) -> Result<Card<AnyType>> {
+        if let Some(reason) = self.conversion_blocker(conversion.card_type()) {
+            bail!(
+                "can't convert '{}' into {}: {}",
+                self.print(),
+                conversion.card_type().name(),
+                reason
+            );
+        }
+
+        let front = self.data.display_front();
+        let old_back = self.back_side().cloned();
+        let old_class = match &self.data {
+            AnyType::Class(class) => Some(class.clone()),
+            _ => None,
+        };
+
+        let data: AnyType = match conversion {
+            Conversion::Normal { back } => {
+                let Some(back) = back.or(old_back) else {
+                    bail!("a normal card needs a back side, '{}' has none", front);
+                };
+                NormalCard { front, back }.into()
+            }
+            Conversion::Unfinished => UnfinishedCard { front }.into(),
+            Conversion::Class { parent_class, back } => {
+                if let Some(parent_class) = parent_class {
+                    self.verify_class(parent_class)?;
+                }
+                ClassCard {
+                    name: front,
+                    back: back.or(old_back).unwrap_or_default(),
+                    parent_class: parent_class
+                        .or(old_class.as_ref().and_then(|class| class.parent_class)),
+                    is_event: old_class.is_some_and(|class| class.is_event),
+                }
+                .into()
+            }
+            Conversion::Instance { class } => {
+                self.verify_class(class)?;
+                InstanceCard { name: front, class }.into()
+            }
+            Conversion::Attribute {
+                attribute,
+                instance,
+                back,
+            } => {
+                if instance == self.id() || Card::from_id(instance).is_none() {
+                    bail!("invalid instance for attribute card: {}", instance);
+                }
+                let Some(back) = back.or(old_back) else {
+                    bail!("an attribute card needs a back side, '{}' has none", front);
+                };
+                let card = AttributeCard {
+                    attribute,
+                    back,
+                    instance,
+                };
+                card.validate()?;
+                card.into()
+            }
+            Conversion::Statement => StatementCard { front }.into(),
+            Conversion::Event {
+                start_time,
+                end_time,
+            } => EventCard {
+                front,
+                start_time,
+                end_time,
+            }
+            .into(),
+        };
+
+        for dependency in data.get_dependencies() {
+            if let Some(err) = crate::graph::cycle_error(self.id(), dependency) {
+                bail!(err);
+            }
+        }
+
+        Ok(self.into_type(data))
+    }
+
+    /// Sets or removes the parent of a class card.
+    pub fn set_parent_class(self, parent_class: Option<CardId>) -> Result<Card<AnyType>> {
+        let AnyType::Class(class) = &self.data else {
+            bail!("'{}' is not a class", self.print());
+        };
+
+        if let Some(parent_class) = parent_class {
+            self.verify_class(parent_class)?;
+            if let Some(err) = crate::graph::cycle_error(self.id(), parent_class) {
+                bail!(err);
+            }
+        }
+
+        let data = ClassCard {
+            parent_class,
+            ..class.clone()
+        };
+        Ok(self.into_type(data.into()))
+    }
+
+    /// Checks that the card would still be valid if its references to `old` pointed at `new`,
+    /// see [`Card::replace_reference`].
+    pub fn verify_replace_reference(&self, old: CardId, new: CardId) -> Result<()> {
+        match self.data.clone().replace_reference(old, new) {
+            AnyType::Instance(InstanceCard { class, .. }) => self.verify_class(class),
+            AnyType::Class(ClassCard {
+                parent_class: Some(parent_class),
+                ..
+            }) => self.verify_class(parent_class),
+            AnyType::Attribute(card) => {
+                if card.instance == self.id() || Card::from_id(card.instance).is_none() {
+                    bail!("invalid instance for attribute card: {}", card.instance);
+                }
+                card.validate()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn verify_class(&self, class: CardId) -> Result<()> {
+        if class == self.id() {
+            bail!("'{}' can't be its own class", self.print());
+        }
+
+        match Card::from_id(class) {
+            Some(card) if card.is_class() => Ok(()),
+            Some(card) => bail!("'{}' is not a class", card.print()),
+            None => bail!("no card with id {}", class),
+        }
+    }
+}
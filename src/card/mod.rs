@@ -2,6 +2,7 @@ use crate::attribute::Attribute;
 use crate::attribute::AttributeId;
 use crate::categories::Category;
 use crate::common::{open_file_with_vim, system_time_as_unix_time};
+use crate::filter::FilterContext;
 use crate::reviews::{Recall, Review, Reviews};
 use crate::{common::current_time, common::CardId};
 use filecash::FsLoad;
@@ -14,6 +15,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::OsString;
 use std::fmt::{Debug, Display};
 use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 use std::time::Duration;
 use uuid::Uuid;
 
@@ -21,10 +23,30 @@ pub type RecallRate = f32;
 
 mod back_side;
 mod card_types;
+mod conversion;
+mod revisions;
 mod serializing;
 
 pub use back_side::*;
 pub use card_types::*;
+pub use conversion::*;
+use revisions::record_revision;
+pub use revisions::*;
+
+/// See [`Card::find_attribute_card`].
+static ATTRIBUTE_CARDS: RwLock<Option<BTreeMap<(AttributeId, CardId), CardId>>> = RwLock::new(None);
+
+fn is_for(raw: &RawCard, (attribute, instance): (AttributeId, CardId)) -> bool {
+    raw.data.attribute == Some(attribute.into_inner())
+        && raw.data.instance == Some(instance.into_inner())
+}
+
+/// Keeps [`Card::find_attribute_card`] up to date, call after a card file was written.
+fn card_saved(raw: &RawCard) {
+    if raw.data.attribute.is_some() {
+        Card::invalidate_attribute_cards();
+    }
+}
 
 #[derive(Ord, PartialOrd, Eq, PartialEq, Hash, Clone, Debug)]
 pub struct CardLocation {
@@ -108,6 +130,18 @@ pub enum AnyType {
 }
 
 impl AnyType {
+    pub fn back_side(&self) -> Option<&BackSide> {
+        match self {
+            AnyType::Attribute(card) => Some(&card.back),
+            AnyType::Normal(card) => Some(&card.back),
+            AnyType::Class(card) => Some(&card.back),
+            AnyType::Unfinished(_) => None?,
+            AnyType::Statement(_) => None?,
+            AnyType::Instance(_) => None?,
+            AnyType::Event(_) => None?,
+        }
+    }
+
     pub fn type_name(&self) -> &str {
         match self {
             AnyType::Unfinished(_) => "unfinished",
@@ -126,15 +160,21 @@ impl AnyType {
     pub fn is_instance(&self) -> bool {
         matches!(self, Self::Instance(_))
     }
+    /// Attribute cards with an empty answer, like the generated ones, count as unfinished.
     pub fn is_finished(&self) -> bool {
-        !matches!(self, Self::Unfinished(_))
+        match self {
+            Self::Unfinished(_) => false,
+            Self::Attribute(card) => !matches!(&card.back, BackSide::Text(s) if s.is_empty()),
+            _ => true,
+        }
     }
 
-    pub fn set_backside(self, new_back: BackSide) -> Self {
-        match self {
-            x @ AnyType::Event(_) => x,
-            x @ AnyType::Instance(_) => x,
-            x @ AnyType::Statement(_) => x,
+    /// Replaces the backside, unfinished cards become normal cards.
+    pub fn set_backside(self, new_back: BackSide) -> eyre::Result<Self> {
+        let card = match self {
+            AnyType::Event(_) | AnyType::Instance(_) | AnyType::Statement(_) => {
+                eyre::bail!("{} cards don't have a back side", self.type_name())
+            }
             AnyType::Normal(NormalCard { front, .. }) => NormalCard {
                 front,
                 back: new_back,
@@ -149,12 +189,15 @@ impl AnyType {
                 attribute,
                 instance: concept_card,
                 ..
-            }) => AttributeCard {
-                attribute,
-                back: new_back,
-                instance: concept_card,
+            }) => {
+                let card = AttributeCard {
+                    attribute,
+                    back: new_back,
+                    instance: concept_card,
+                };
+                card.validate()?;
+                card.into()
             }
-            .into(),
             Self::Class(class) => ClassCard {
                 name: class.name,
                 back: new_back,
@@ -162,6 +205,70 @@ impl AnyType {
                 is_event: class.is_event,
             }
             .into(),
+        };
+
+        Ok(card)
+    }
+}
+
+impl BackSide {
+    fn replace_reference(self, old: CardId, new: CardId) -> Self {
+        match self {
+            BackSide::Card(id) if id == old => BackSide::Card(new),
+            BackSide::List(ids) => {
+                let mut replaced = vec![];
+                for id in ids {
+                    let id = if id == old { new } else { id };
+                    if !replaced.contains(&id) {
+                        replaced.push(id);
+                    }
+                }
+                BackSide::List(replaced)
+            }
+            other => other,
+        }
+    }
+}
+
+impl AnyType {
+    /// Points every reference to the `old` card at the `new` one instead.
+    pub fn replace_reference(self, old: CardId, new: CardId) -> Self {
+        let replace = |id: CardId| if id == old { new } else { id };
+
+        match self {
+            AnyType::Instance(InstanceCard { name, class }) => InstanceCard {
+                name,
+                class: replace(class),
+            }
+            .into(),
+            AnyType::Normal(NormalCard { front, back }) => NormalCard {
+                front,
+                back: back.replace_reference(old, new),
+            }
+            .into(),
+            AnyType::Attribute(AttributeCard {
+                attribute,
+                back,
+                instance,
+            }) => AttributeCard {
+                attribute,
+                back: back.replace_reference(old, new),
+                instance: replace(instance),
+            }
+            .into(),
+            AnyType::Class(ClassCard {
+                name,
+                back,
+                parent_class,
+                is_event,
+            }) => ClassCard {
+                name,
+                back: back.replace_reference(old, new),
+                parent_class: parent_class.map(replace),
+                is_event,
+            }
+            .into(),
+            other @ (AnyType::Unfinished(_) | AnyType::Statement(_) | AnyType::Event(_)) => other,
         }
     }
 }
@@ -215,11 +322,9 @@ impl<T: CardTrait> std::fmt::Display for Card<T> {
 }
 
 impl Card<AttributeCard> {
-    pub fn new(attr: AttributeCard, category: &Category) -> Card<AnyType> {
-        let raw = RawCard::new_attribute(attr);
-        let id = raw.id;
-        raw.save_at(&category.as_path());
-        Card::from_raw(RawCard::load(id).unwrap())
+    pub fn new(attr: AttributeCard, category: &Category) -> eyre::Result<Card<AnyType>> {
+        attr.validate()?;
+        Ok(Card::save_new(RawCard::new_attribute(attr), category))
     }
 }
 
@@ -243,13 +348,17 @@ impl Card<AnyType> {
 
     /// Loads all the ancestor ancestor classes
     /// for example, king, human male, human
+    /// Stops if the chain of classes loops back on itself or a class is missing.
     pub fn load_belonging_classes(&self) -> Vec<CardId> {
         let mut classes = vec![];
         let mut parent_class = self.class();
 
         while let Some(class) = parent_class {
+            if class == self.id() || classes.contains(&class) {
+                break;
+            }
             classes.push(class);
-            parent_class = Card::from_id(class).unwrap().class();
+            parent_class = Card::from_id(class).and_then(|card| card.class());
         }
 
         classes
@@ -264,11 +373,14 @@ impl Card<AnyType> {
             .collect()
     }
 
-    pub fn set_ref(mut self, reff: CardId) -> Card<AnyType> {
+    pub fn set_ref(mut self, reff: CardId) -> eyre::Result<Card<AnyType>> {
+        if let Some(err) = crate::graph::cycle_error(self.id(), reff) {
+            eyre::bail!(err);
+        }
         let backside = BackSide::Card(reff);
-        self.data = self.data.set_backside(backside);
+        self.data = self.data.set_backside(backside)?;
         self.persist();
-        self
+        Ok(self)
     }
 
     // potentially expensive function!
@@ -291,7 +403,12 @@ impl Card<AnyType> {
     // Call this function every time SavedCard is mutated.
     pub fn persist(&mut self) {
         self.history.save(self.id());
-        RawCard::from_card(self.clone()).save();
+        let raw = RawCard::from_card(self.clone());
+        if let Some(old) = RawCard::load(self.id().into_inner()) {
+            record_revision(&old, &raw);
+        }
+        raw.save();
+        card_saved(&raw);
         *self = Self::from_raw(RawCard::load(self.id().into_inner()).unwrap());
     }
 
@@ -321,46 +438,149 @@ impl Card<AnyType> {
         }
     }
 
+    /// Reads the id of the card stored in the file, failing if it isn't a valid card.
+    pub fn verify_file(path: &Path) -> eyre::Result<CardId> {
+        let s = std::fs::read_to_string(path)?;
+        let raw: RawCard = toml::from_str(&s)?;
+        raw.data.try_into_any().map_err(|e| eyre::eyre!(e))?;
+        Ok(CardId(raw.id))
+    }
+
+    /// Replaces the back side, fails for card types that don't have one.
+    pub fn set_back_side(&mut self, back: BackSide) -> eyre::Result<()> {
+        self.data = self.data.clone().set_backside(back)?;
+        self.persist();
+        Ok(())
+    }
+
+    /// Points every reference this card has to the `old` card at the `new` one instead,
+    /// both in its dependencies and in its type specific fields.
+    pub fn replace_reference(mut self, old: CardId, new: CardId) -> Card<AnyType> {
+        if self.dependencies.remove(&old) && new != self.id {
+            self.dependencies.insert(new);
+        }
+        self.data = self.data.replace_reference(old, new);
+        self.persist();
+        self
+    }
+
+    /// Takes over the dependencies, tags and reviews of the other card.
+    /// On conflicting tag values this card's value is kept.
+    pub fn absorb(&mut self, other: &Card<AnyType>) {
+        self.dependencies.extend(other.dependencies.iter().copied());
+        self.dependencies.remove(&self.id);
+        self.dependencies.remove(&other.id);
+
+        for (tag, value) in &other.tags {
+            self.tags
+                .entry(tag.clone())
+                .or_insert_with(|| value.clone());
+        }
+
+        self.history.merge(&other.history);
+        self.persist();
+    }
+
+    /// Dependencies that were added explicitly, as opposed to those implied by the card type.
+    pub fn explicit_dependencies(&self) -> &BTreeSet<CardId> {
+        &self.dependencies
+    }
+
     pub fn save_at(raw_card: RawCard, path: &Path) -> Card<AnyType> {
         let id = raw_card.id;
         raw_card.save_at(path);
         let raw_card = RawCard::load(id).unwrap();
+        card_saved(&raw_card);
         Self::from_raw(raw_card)
     }
 
-    pub fn new_normal(unfinished: NormalCard, category: &Category) -> Card<AnyType> {
-        let raw_card = RawCard::new(unfinished);
+    /// Saves a newly created card in the category, giving it the category's default tags.
+    fn save_new(mut raw_card: RawCard, category: &Category) -> Card<AnyType> {
+        for (tag, value) in category.default_tags() {
+            raw_card.tags.entry(tag).or_insert(value);
+        }
         raw_card.save_at(&category.as_path());
         let raw_card = RawCard::load(raw_card.id).unwrap();
+        card_saved(&raw_card);
         Self::from_raw(raw_card)
     }
 
+    pub fn new_normal(unfinished: NormalCard, category: &Category) -> Card<AnyType> {
+        let raw_card = RawCard::new(unfinished);
+        Self::save_new(raw_card, category)
+    }
+
     pub fn new_class(class: ClassCard, category: &Category) -> Card<AnyType> {
         let raw_card = RawCard::new(class);
-        raw_card.save_at(&category.as_path());
-        let raw_card = RawCard::load(raw_card.id).unwrap();
-        Self::from_raw(raw_card)
+        Self::save_new(raw_card, category)
     }
 
-    pub fn new_attribute(unfinished: AttributeCard, category: &Category) -> Card<AnyType> {
+    /// Fails if the back side doesn't match the attribute's back type.
+    pub fn new_attribute(
+        unfinished: AttributeCard,
+        category: &Category,
+    ) -> eyre::Result<Card<AnyType>> {
+        unfinished.validate()?;
         let raw_card = RawCard::new(unfinished);
-        raw_card.save_at(&category.as_path());
-        let raw_card = RawCard::load(raw_card.id).unwrap();
-        Self::from_raw(raw_card)
+        Ok(Self::save_new(raw_card, category))
+    }
+
+    /// Finds the attribute card for the given attribute and instance, if one exists.
+    ///
+    /// Looks at the raw files so it's safe to call while rendering card names. The attribute cards
+    /// are indexed the first time and again after an attribute card is saved, as this is called
+    /// for every `{attr:}` placeholder that's rendered.
+    pub fn find_attribute_card(attribute: AttributeId, instance: CardId) -> Option<CardId> {
+        let key = (attribute, instance);
+        let cached = ATTRIBUTE_CARDS
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|cards| cards.get(&key).copied());
+
+        match cached {
+            Some(None) => return None,
+            // The card might have been deleted or changed behind our back.
+            Some(Some(id))
+                if RawCard::load(id.into_inner()).is_some_and(|raw| is_for(&raw, key)) =>
+            {
+                return Some(id)
+            }
+            _ => {}
+        }
+
+        let cards: BTreeMap<(AttributeId, CardId), CardId> = RawCard::load_all()
+            .into_iter()
+            .filter_map(|raw| {
+                let attribute = AttributeId::verify(raw.data.attribute?)?;
+                let instance = CardId(raw.data.instance?);
+                Some(((attribute, instance), CardId(raw.id)))
+            })
+            .collect();
+        let found = cards.get(&key).copied();
+        *ATTRIBUTE_CARDS.write().unwrap() = Some(cards);
+        found
+    }
+
+    /// Makes [`Card::find_attribute_card`] look at the files again, for when cards were added
+    /// or edited outside of this library.
+    pub fn invalidate_attribute_cards() {
+        *ATTRIBUTE_CARDS.write().unwrap() = None;
     }
 
     pub fn new_instance(instance: InstanceCard, category: &Category) -> Card<AnyType> {
         let raw_card = RawCard::new(instance);
-        raw_card.save_at(&category.as_path());
-        let raw_card = RawCard::load(raw_card.id).unwrap();
-        Self::from_raw(raw_card)
+        Self::save_new(raw_card, category)
+    }
+
+    pub fn new_event(event: EventCard, category: &Category) -> Card<AnyType> {
+        let raw_card = RawCard::new(event);
+        Self::save_new(raw_card, category)
     }
 
     pub fn new_unfinished(unfinished: UnfinishedCard, category: &Category) -> Card<AnyType> {
         let raw_card = RawCard::new(unfinished);
-        raw_card.save_at(&category.as_path());
-        let raw_card = RawCard::load(raw_card.id).unwrap();
-        Self::from_raw(raw_card)
+        Self::save_new(raw_card, category)
     }
 
     pub fn load_all_cards() -> Vec<Card<AnyType>> {
@@ -378,33 +598,27 @@ impl Card<AnyType> {
     }
 
     pub fn load_pending(filter: Option<String>) -> Vec<CardId> {
-        Self::load_all_cards()
-            .into_par_iter()
-            .filter(|card| card.history().is_empty())
-            .filter(|card| {
-                if let Some(ref filter) = filter {
-                    card.eval(filter.clone())
-                } else {
-                    true
-                }
-            })
-            .map(|card| card.id())
-            .collect()
+        Self::load_filtered(filter, true)
     }
 
     pub fn load_non_pending(filter: Option<String>) -> Vec<CardId> {
-        Self::load_all_cards()
-            .into_par_iter()
-            .filter(|card| !card.history().is_empty())
-            .filter(|card| {
-                if let Some(ref filter) = filter {
-                    card.eval(filter.clone())
-                } else {
-                    true
-                }
-            })
-            .map(|card| card.id())
-            .collect()
+        Self::load_filtered(filter, false)
+    }
+
+    fn load_filtered(filter: Option<String>, pending: bool) -> Vec<CardId> {
+        match filter {
+            Some(filter) => FilterContext::load()
+                .matching(&filter)
+                .into_iter()
+                .filter(|card| card.history().is_empty() == pending)
+                .map(|card| card.id())
+                .collect(),
+            None => Self::load_all_cards()
+                .into_iter()
+                .filter(|card| card.history().is_empty() == pending)
+                .map(|card| card.id())
+                .collect(),
+        }
     }
 
     pub fn rm_dependency(&mut self, dependency: CardId) -> bool {
@@ -413,43 +627,79 @@ impl Card<AnyType> {
         res
     }
 
-    pub fn set_dependency(&mut self, dependency: CardId) {
-        if self.id() == dependency {
-            return;
+    /// Fails if the dependency would create a cycle, the error names the cards in it.
+    pub fn set_dependency(&mut self, dependency: CardId) -> eyre::Result<()> {
+        if let Some(err) = crate::graph::cycle_error(self.id(), dependency) {
+            eyre::bail!(err);
         }
         self.dependencies.insert(dependency);
         self.persist();
+        Ok(())
+    }
+
+    pub fn add_tag(&mut self, tag: &str, value: &str) -> eyre::Result<()> {
+        let tag = crate::tags::normalize_tag(tag)?;
+        self.tags.insert(tag, value.to_string());
+        self.persist();
+        Ok(())
+    }
+
+    /// Removes the tag and any tags nested under it, returns false if there was nothing to remove.
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let len = self.tags.len();
+        self.tags.retain(|key, _| !crate::tags::is_within(key, tag));
+        if self.tags.len() == len {
+            return false;
+        }
+        self.persist();
+        true
     }
 
     pub fn edit_with_vim(&self) -> Card<AnyType> {
         let path = self.as_path();
+        let old = RawCard::load(self.id().into_inner());
         open_file_with_vim(path.as_path()).unwrap();
-        Self::from_raw(RawCard::load(self.id().into_inner()).unwrap())
+        let new = RawCard::load(self.id().into_inner()).unwrap();
+        if let Some(old) = old {
+            record_revision(&old, &new);
+        }
+        card_saved(&new);
+        Self::from_raw(new)
     }
 
     pub fn new_review(&mut self, grade: Recall, time: Duration) {
-        let review = Review::new(grade, time);
+        let mut review = Review::new(grade, time);
+        let last_review = self.history.0.last().map(|review| review.timestamp);
+        review.content_changed = self.revisions().iter().any(|revision| {
+            revision.significant
+                && last_review.map_or(false, |last| Duration::from_secs(revision.timestamp) > last)
+        });
         self.history.add_review(review);
         self.persist();
     }
 
     pub fn back_side(&self) -> Option<&BackSide> {
-        match self.card_type() {
-            AnyType::Attribute(card) => Some(&card.back),
-            AnyType::Normal(card) => Some(&card.back),
-            AnyType::Class(card) => Some(&card.back),
-            AnyType::Unfinished(_) => None?,
-            AnyType::Statement(_) => None?,
-            AnyType::Instance(_) => None?,
-            AnyType::Event(_) => None?,
+        self.data.back_side()
+    }
+
+    /// Media files referenced from the front or a text backside.
+    pub fn media(&self) -> Vec<crate::media::MediaRef> {
+        let mut refs = crate::media::references_in(&self.data.display_front());
+        if let Some(BackSide::Text(back)) = self.back_side() {
+            refs.extend(crate::media::references_in(back));
         }
+        refs
     }
 
     pub fn into_type(self, data: impl Into<AnyType>) -> Card<AnyType> {
         let id = self.id();
         let mut raw = RawCard::from_card(self);
         raw.data = RawType::from_any(data.into());
+        if let Some(old) = RawCard::load(id.into_inner()) {
+            record_revision(&old, &raw);
+        }
         raw.save();
+        card_saved(&raw);
         Card::from_id(id).unwrap()
     }
 }
@@ -482,6 +732,18 @@ impl<T: CardTrait> Card<T> {
         crate::recall_rate::recall_rate(&self.history, now)
     }
 
+    /// Days until the card is due for review, using the target recall of its category.
+    ///
+    /// This reads the category settings, [`FilterContext::due_in`] has them cached for many cards.
+    pub fn due_in(&self) -> Option<f32> {
+        self.due_in_with_target(self.category().target_recall())
+    }
+
+    /// Days until the recall of the card drops to the target.
+    pub fn due_in_with_target(&self, target_recall: f32) -> Option<f32> {
+        crate::recall_rate::due_in_days(&self.history, current_time(), target_recall)
+    }
+
     fn is_resolved(&self) -> bool {
         for id in self.all_dependencies() {
             if let Some(card) = Card::from_id(id) {
@@ -494,22 +756,25 @@ impl<T: CardTrait> Card<T> {
         true
     }
 
+    /// All the direct and indirect dependencies, each listed once even if the graph has cycles.
     pub fn all_dependencies(&self) -> Vec<CardId> {
-        fn inner(id: CardId, deps: &mut Vec<CardId>) {
+        let mut deps = vec![];
+        let mut visited = BTreeSet::from([self.id()]);
+        let mut stack = vec![self.id()];
+
+        while let Some(id) = stack.pop() {
             let Some(card) = Card::from_id(id) else {
-                return;
+                continue;
             };
 
             for dep in card.dependency_ids() {
-                deps.push(dep);
-                inner(dep, deps);
+                if visited.insert(dep) {
+                    deps.push(dep);
+                    stack.push(dep);
+                }
             }
         }
 
-        let mut deps = vec![];
-
-        inner(self.id(), &mut deps);
-
         deps
     }
 
@@ -546,7 +811,6 @@ impl<T: CardTrait> Card<T> {
         &self.location.category
     }
 
-    #[allow(dead_code)]
     pub fn is_pending(&self) -> bool {
         self.history.is_empty()
     }
@@ -595,6 +859,16 @@ impl<T: CardTrait> Card<T> {
     pub fn lapses(&self) -> u32 {
         self.history.lapses()
     }
+
+    /// Tags of the card, tags without a value map to an empty string.
+    pub fn tags(&self) -> &BTreeMap<String, String> {
+        &self.tags
+    }
+
+    /// Whether the card has the tag or one nested under it, so `lang` matches `lang::german`.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.keys().any(|key| crate::tags::is_within(key, tag))
+    }
 }
 
 impl Matcher for Card<AnyType> {
@@ -640,6 +914,45 @@ impl Matcher for Card<AnyType> {
                 json!(min_recall as f32 / 1000.)
             }
             "dependencies" => json!(self.dependency_ids().len()),
+            "tags" => json!(self.tags.len()),
+            key if key.starts_with("tag:") => json!(self.has_tag(&key["tag:".len()..])),
+            key if key.starts_with("tagvalue:") => json!(self
+                .tags
+                .get(&key["tagvalue:".len()..])
+                .cloned()
+                .unwrap_or_default()),
+            "type" => json!(self.data.type_name()),
+            "category" => json!(self.category().joined()),
+            "collection" => json!(self.category().collection_name().unwrap_or_default()),
+            "class" => json!(self
+                .class()
+                .map(|class| class.to_string())
+                .unwrap_or_default()),
+            // The class or any of its parent classes, by id or name.
+            key if key.starts_with("ancestor_class:") => {
+                let class = &key["ancestor_class:".len()..];
+                json!(self.load_belonging_classes().into_iter().any(|id| {
+                    id.to_string() == class
+                        || Card::from_id(id).is_some_and(|card| card.print() == class)
+                }))
+            }
+            "attribute" => json!(match &self.data {
+                AnyType::Attribute(attr) => attr.attribute.into_inner().to_string(),
+                _ => String::new(),
+            }),
+            "pending" => json!(self.is_pending()),
+            "reviews" => json!(self.history.len()),
+            // Null for cards that haven't been reviewed, so they're not taken as due now.
+            "age" => json!(self
+                .history
+                .time_since_first_review(current_time())
+                .map(|age| age.as_secs_f32() / 86400.)),
+            "due_in" => json!(self.due_in()),
+            "last_grade" => json!(self.history.0.last().map(|review| review.grade.as_number())),
+            "avg_time_spent" => json!(self
+                .history
+                .average_time_spent()
+                .map(|spent| spent.as_secs_f32())),
             "dependents" => {
                 let id = self.id();
                 let mut count: usize = 0;
@@ -0,0 +1,258 @@
+use super::serializing::RawCard;
+use super::*;
+use crate::answer::normalize;
+use crate::duplicates::similarity;
+use crate::paths::get_revisions_path;
+use serde::{Deserialize, Serialize};
+
+/// Below this similarity between the old and new front or back, the change counts as significant.
+const SIGNIFICANT_SIMILARITY: f32 = 0.8;
+
+/// A previous version of a card, saved when the card was changed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Revision {
+    /// Unix time in seconds of when the card was changed from this version.
+    pub timestamp: u64,
+    pub author: String,
+    /// The fields that were changed from this version: front, back, type, dependencies or tags.
+    pub changed: Vec<String>,
+    /// Whether the front, back or type changed enough that reviews of this version
+    /// don't say much about the next one.
+    pub significant: bool,
+    /// The card file as it was.
+    content: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct RevisionLog {
+    #[serde(default)]
+    revisions: Vec<Revision>,
+}
+
+/// A field that differs between two versions of a card.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+/// The parts of a card version that revisions keep track of.
+struct Snapshot {
+    front: String,
+    back: String,
+    type_name: String,
+    dependencies: String,
+    tags: String,
+}
+
+impl Snapshot {
+    fn new(raw: &RawCard) -> Option<Self> {
+        let data = raw.data.clone().try_into_any().ok()?;
+        let back = data.back_side().map(describe_back).unwrap_or_default();
+
+        let mut dependencies: Vec<String> = raw
+            .dependencies
+            .iter()
+            .map(|id| describe_card(CardId(*id)))
+            .collect();
+        dependencies.sort();
+
+        let tags: Vec<String> = raw
+            .tags
+            .iter()
+            .map(|(tag, value)| {
+                if value.is_empty() {
+                    tag.clone()
+                } else {
+                    format!("{}: {}", tag, value)
+                }
+            })
+            .collect();
+
+        Some(Self {
+            front: data.display_front(),
+            back,
+            type_name: data.type_name().to_string(),
+            dependencies: dependencies.join(", "),
+            tags: tags.join(", "),
+        })
+    }
+
+    fn fields(&self) -> [(&'static str, &String); 5] {
+        [
+            ("front", &self.front),
+            ("back", &self.back),
+            ("type", &self.type_name),
+            ("dependencies", &self.dependencies),
+            ("tags", &self.tags),
+        ]
+    }
+
+    fn diff(&self, new: &Self) -> Vec<FieldChange> {
+        self.fields()
+            .into_iter()
+            .zip(new.fields())
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((field, old), (_, new))| FieldChange {
+                field,
+                old: old.clone(),
+                new: new.clone(),
+            })
+            .collect()
+    }
+
+    fn is_significant_change(&self, new: &Self) -> bool {
+        self.type_name != new.type_name
+            || similarity(&normalize(&self.front), &normalize(&new.front)) < SIGNIFICANT_SIMILARITY
+            || similarity(&normalize(&self.back), &normalize(&new.back)) < SIGNIFICANT_SIMILARITY
+    }
+}
+
+/// Referenced cards by name, or by id if they no longer exist.
+fn describe_card(id: CardId) -> String {
+    Card::from_id(id)
+        .map(|card| card.print())
+        .unwrap_or_else(|| id.to_string())
+}
+
+fn describe_back(back: &BackSide) -> String {
+    match back {
+        BackSide::Text(text) => text.clone(),
+        BackSide::Time(time) => time.to_string(),
+        BackSide::Card(id) => describe_card(*id),
+        BackSide::List(ids) => ids
+            .iter()
+            .map(|id| describe_card(*id))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// The git user name, or the user name of the system.
+fn current_author() -> String {
+    git2::Config::open_default()
+        .and_then(|config| config.get_string("user.name"))
+        .ok()
+        .or_else(|| std::env::var("USER").ok())
+        .or_else(|| std::env::var("USERNAME").ok())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn log_path(id: CardId) -> PathBuf {
+    get_revisions_path().join(id.to_string())
+}
+
+fn load_log(id: CardId) -> RevisionLog {
+    std::fs::read_to_string(log_path(id))
+        .ok()
+        .and_then(|s| toml::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Adds the old version to the card's revision log if anything it keeps track of changed.
+pub(super) fn record_revision(old: &RawCard, new: &RawCard) {
+    // Most saves are for reviews or suspending, which don't touch the content.
+    let same_data = toml::to_string(&old.data).ok() == toml::to_string(&new.data).ok();
+    if same_data && old.dependencies == new.dependencies && old.tags == new.tags {
+        return;
+    }
+
+    let (Some(old_snapshot), Some(new_snapshot)) = (Snapshot::new(old), Snapshot::new(new)) else {
+        return;
+    };
+
+    let changed: Vec<String> = old_snapshot
+        .diff(&new_snapshot)
+        .into_iter()
+        .map(|change| change.field.to_string())
+        .collect();
+    if changed.is_empty() {
+        return;
+    }
+
+    let Ok(content) = toml::to_string_pretty(old) else {
+        return;
+    };
+
+    let id = CardId(old.id);
+    let mut log = load_log(id);
+    log.revisions.push(Revision {
+        timestamp: current_time().as_secs(),
+        author: current_author(),
+        changed,
+        significant: old_snapshot.is_significant_change(&new_snapshot),
+        content,
+    });
+
+    if let Ok(s) = toml::to_string_pretty(&log) {
+        let _ = std::fs::write(log_path(id), s);
+    }
+}
+
+impl Revision {
+    fn raw(&self) -> eyre::Result<RawCard> {
+        Ok(toml::from_str(&self.content)?)
+    }
+
+    pub fn front(&self) -> Option<String> {
+        let raw = self.raw().ok()?;
+        Some(raw.data.try_into_any().ok()?.display_front())
+    }
+
+    /// What changed from this version to the other one.
+    pub fn diff(&self, other: &Revision) -> eyre::Result<Vec<FieldChange>> {
+        diff_raw(&self.raw()?, &other.raw()?)
+    }
+}
+
+fn diff_raw(old: &RawCard, new: &RawCard) -> eyre::Result<Vec<FieldChange>> {
+    match (Snapshot::new(old), Snapshot::new(new)) {
+        (Some(old), Some(new)) => Ok(old.diff(&new)),
+        _ => eyre::bail!("invalid card version"),
+    }
+}
+
+impl Card<AnyType> {
+    /// Previous versions of the card, oldest first.
+    pub fn revisions(&self) -> Vec<Revision> {
+        load_log(self.id()).revisions
+    }
+
+    /// What changed from the revision at `index` to the card as it is now.
+    pub fn diff_with_revision(&self, index: usize) -> eyre::Result<Vec<FieldChange>> {
+        let revisions = self.revisions();
+        let Some(revision) = revisions.get(index) else {
+            eyre::bail!("card {} has no revision {}", self.id(), index);
+        };
+        diff_raw(&revision.raw()?, &RawCard::from_card(self.clone()))
+    }
+
+    /// Brings back the content of the revision at `index`, the version it replaces is kept
+    /// as a new revision so the restore can be undone. Reviews and the suspended state stay as they are.
+    pub fn restore_revision(mut self, index: usize) -> eyre::Result<Card<AnyType>> {
+        let revisions = self.revisions();
+        let Some(revision) = revisions.get(index) else {
+            eyre::bail!("card {} has no revision {}", self.id(), index);
+        };
+
+        let raw = revision.raw()?;
+        let data = raw.data.try_into_any().map_err(|e| eyre::eyre!(e))?;
+        let dependencies: BTreeSet<CardId> = raw.dependencies.into_iter().map(CardId).collect();
+
+        let current = self.dependency_ids();
+        for dep in dependencies.iter().chain(&data.get_dependencies()) {
+            if !current.contains(dep) {
+                if let Some(err) = crate::graph::cycle_error(self.id(), *dep) {
+                    eyre::bail!(err);
+                }
+            }
+        }
+
+        self.data = data;
+        self.dependencies = dependencies;
+        self.tags = raw.tags;
+        self.persist();
+        Ok(self)
+    }
+}
@@ -39,8 +39,12 @@ pub struct RawType {
 
 impl RawType {
     pub fn into_any(self) -> AnyType {
+        self.try_into_any().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_into_any(self) -> Result<AnyType, String> {
         if let Some(statement) = self.statement {
-            return StatementCard { front: statement }.into();
+            return Ok(StatementCard { front: statement }.into());
         }
 
         if let Some(event) = self.event {
@@ -50,21 +54,17 @@ impl RawType {
                 .map(TimeStamp::from_string)
                 .flatten()
                 .unwrap_or_default();
-            let end_time = self
-                .start_time
-                .clone()
-                .map(TimeStamp::from_string)
-                .flatten();
+            let end_time = self.end_time.clone().map(TimeStamp::from_string).flatten();
 
-            return EventCard {
+            return Ok(EventCard {
                 front: event,
                 start_time,
                 end_time,
             }
-            .into();
+            .into());
         }
 
-        match (
+        let card = match (
             self.front,
             self.back,
             self.name,
@@ -73,7 +73,8 @@ impl RawType {
             self.instance,
         ) {
             (None, Some(back), None, None, Some(attribute), Some(instance)) => AttributeCard {
-                attribute: AttributeId::verify(&attribute).unwrap(),
+                attribute: AttributeId::verify(&attribute)
+                    .ok_or_else(|| format!("attribute not found: {}", attribute))?,
                 back,
                 instance: CardId(instance),
             }
@@ -93,9 +94,11 @@ impl RawType {
             }
             .into(),
             other => {
-                panic!("invalid combination of args: {:?}", other);
+                return Err(format!("invalid combination of args: {:?}", other));
             }
-        }
+        };
+
+        Ok(card)
     }
 
     pub fn from_any(ty: AnyType) -> Self {
@@ -1,7 +1,21 @@
-use crate::collections::Collection;
+use crate::attribute::Attribute;
+use crate::card::AnyType;
+use crate::collections::{get_dirs, Collection};
+use crate::common::CardId;
+use crate::graph::DependencyGraph;
 use crate::paths::{self};
+use crate::trash::trash_cards;
+use crate::Card;
+use eyre::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
+use walkdir::WalkDir;
+
+/// Metadata of a category, no extension so it's not mistaken for a card file.
+const META_FILE: &str = ".category";
 
 // Represent the category that a card is in, can be nested
 #[derive(Ord, PartialOrd, Eq, Hash, Debug, Clone, PartialEq, Default)]
@@ -16,56 +30,82 @@ impl Category {
         self
     }
 
+    /// The top level category of the collection, or of the personal cards if `None`.
+    pub fn root(collection: Option<&Collection>) -> Self {
+        Self {
+            collection: collection.map(|col| col.name().to_string()),
+            dir: vec![],
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.dir.is_empty()
+    }
+
+    pub fn parent(&self) -> Option<Self> {
+        let mut parent = self.clone();
+        parent.dir.pop()?;
+        Some(parent)
+    }
+
+    pub fn collection_name(&self) -> Option<&str> {
+        self.collection.as_deref()
+    }
+
+    /// The same category path in another collection, or in the personal cards if `None`.
+    pub fn in_collection(&self, collection: Option<&Collection>) -> Self {
+        Self {
+            collection: collection.map(|col| col.name().to_string()),
+            dir: self.dir.clone(),
+        }
+    }
+
+    /// Whether this is the other category or one of its subcategories.
+    pub fn is_within(&self, other: &Self) -> bool {
+        self.collection == other.collection && self.dir.starts_with(&other.dir)
+    }
+
+    /// Directory of the collection the category is in, or the personal cards directory.
+    pub fn collection_path(&self) -> PathBuf {
+        match self.collection.as_ref() {
+            Some(col_name) => paths::get_collections_path().join(col_name),
+            None => paths::get_cards_path(),
+        }
+    }
+
     pub fn joined(&self) -> String {
         self.dir.join("/")
     }
 
-    fn from_dir_path(path: &Path) -> Self {
+    /// The category of a directory under the cards or collections directory, `None` for other paths.
+    /// The directory doesn't have to exist.
+    pub fn from_dir_path(path: &Path) -> Option<Self> {
         // either cards/x/y or collections/colname/x/y
-        let path = path
-            .strip_prefix(paths::get_share_path())
-            .to_owned()
-            .unwrap();
-
-        let mut components = path.components();
+        let path = path.strip_prefix(paths::get_share_path()).ok()?;
+        let mut components = path.components().map(|c| c.as_os_str().to_str());
 
-        let collection = match components.next().unwrap().as_os_str().to_str().unwrap() {
+        let collection = match components.next()?? {
             "cards" => None,
-            "collections" => {
-                let col_name = components
-                    .next()
-                    .unwrap()
-                    .as_os_str()
-                    .to_str()
-                    .unwrap()
-                    .to_owned();
-                Some(col_name)
-            }
-            _ => panic!(),
+            "collections" => Some(components.next()??.to_owned()),
+            _ => return None,
         };
 
         let mut dirs = vec![];
 
         for c in components {
-            let c = c.as_os_str().to_str().unwrap().to_string();
-            dirs.push(c);
+            dirs.push(c?.to_string());
         }
 
-        let categories = Self {
+        Some(Self {
             collection,
             dir: dirs,
-        };
-
-        if categories.as_path().exists() {
-            categories
-        } else {
-            panic!();
-        }
+        })
     }
 
     pub fn from_card_path(path: &Path) -> Self {
         let dir = path.parent().unwrap().to_owned();
         Self::from_dir_path(&dir)
+            .expect("card file outside of the cards and collections directories")
     }
 
     pub fn get_containing_card_paths(&self) -> Vec<PathBuf> {
@@ -127,8 +167,9 @@ impl Category {
             .filter_entry(|e| Self::is_visible_dir(e))
             .filter_map(Result::ok)
         {
-            let cat = Self::from_dir_path(entry.path());
-            output.push(cat);
+            if let Some(cat) = Self::from_dir_path(entry.path()) {
+                output.push(cat);
+            }
         }
 
         output
@@ -136,12 +177,275 @@ impl Category {
 
     pub fn as_path(&self) -> PathBuf {
         let categories = self.dir.join("/");
-        let prefix = match self.collection.as_ref() {
-            Some(col_name) => paths::get_collections_path().join(col_name),
-            None => paths::get_cards_path(),
-        };
+        let prefix = self.collection_path();
 
         let path = format!("{}/{}", prefix.display(), categories);
         PathBuf::from(path)
     }
+
+    pub fn exists(&self) -> bool {
+        self.as_path().is_dir()
+    }
+
+    /// Creates the category directory along with any missing parent categories.
+    pub fn create(&self) -> Result<()> {
+        for name in &self.dir {
+            validate_name(name)?;
+        }
+        if self.exists() {
+            bail!("category {} already exists", self.print_full());
+        }
+        fs::create_dir_all(self.as_path())?;
+        Ok(())
+    }
+
+    /// See [`crate::relocate::rename_category`].
+    pub fn rename(&self, name: &str) -> Result<Self> {
+        crate::relocate::rename_category(self, name)
+    }
+
+    /// Deletes the category directory, returns the cards that were moved to the trash.
+    ///
+    /// Unless `recursive` is set the category has to be empty, otherwise its cards and
+    /// subcategories go too. Either way it fails if cards outside the category depend on it,
+    /// or if it holds files other than cards, such as notes or metadata, as those can't be restored.
+    pub fn delete(&self, recursive: bool) -> Result<Vec<CardId>> {
+        if self.is_root() {
+            bail!("can't delete the top level category");
+        }
+        if !self.exists() {
+            bail!("category {} doesn't exist", self.print_full());
+        }
+
+        let ids: BTreeSet<CardId> = Card::load_all_cards()
+            .into_iter()
+            .filter(|card| card.category().is_within(self))
+            .map(|card| card.id())
+            .collect();
+
+        if !recursive && (!ids.is_empty() || !self.subcategories().is_empty()) {
+            bail!("category {} isn't empty", self.print_full());
+        }
+
+        let graph = DependencyGraph::load();
+        for id in &ids {
+            if let Some(dependent) = graph
+                .dependents(*id)
+                .into_iter()
+                .find(|dependent| !ids.contains(dependent))
+            {
+                bail!(
+                    "can't delete category {}, '{}' depends on '{}'",
+                    self.print_full(),
+                    Card::from_id(dependent).unwrap().print(),
+                    Card::from_id(*id).unwrap().print()
+                );
+            }
+        }
+
+        for class in &ids {
+            for attr in Attribute::load_from_class_only(*class) {
+                if let Some(card) = attr.cards().into_iter().find(|card| !ids.contains(card)) {
+                    bail!(
+                        "can't delete category {}, '{}' is an attribute card of '{}'",
+                        self.print_full(),
+                        Card::from_id(card).unwrap().print(),
+                        Card::from_id(*class).unwrap().print()
+                    );
+                }
+            }
+        }
+
+        // Only cards can be restored from the trash, anything else would be lost for good.
+        let card_paths: BTreeSet<PathBuf> = ids
+            .iter()
+            .filter_map(|id| Card::from_id(*id))
+            .map(|card| card.as_path())
+            .collect();
+        let others: Vec<String> = WalkDir::new(self.as_path())
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file() && !card_paths.contains(entry.path()))
+            .map(|entry| entry.path().display().to_string())
+            .collect();
+        if !others.is_empty() {
+            bail!(
+                "can't delete category {}, it has files that aren't cards: {}",
+                self.print_full(),
+                others.join(", ")
+            );
+        }
+
+        trash_cards(&ids.iter().copied().collect::<Vec<_>>())?;
+
+        fs::remove_dir_all(self.as_path())?;
+        Ok(ids.into_iter().collect())
+    }
+
+    /// The categories directly inside this one.
+    pub fn subcategories(&self) -> Vec<Self> {
+        let path = self.as_path();
+        if !path.is_dir() {
+            return vec![];
+        }
+
+        let mut subcategories: Vec<Self> = get_dirs(&path)
+            .into_iter()
+            .filter_map(|dir| {
+                let name = dir.file_name()?.to_str()?.to_string();
+                (!name.starts_with('.')).then(|| self.clone().join(&name))
+            })
+            .collect();
+        subcategories.sort();
+        subcategories
+    }
+
+    fn meta_path(&self) -> PathBuf {
+        self.as_path().join(META_FILE)
+    }
+
+    /// The metadata stored in this category, not including what it inherits from its parents.
+    pub fn meta(&self) -> CategoryMeta {
+        fs::read_to_string(self.meta_path())
+            .ok()
+            .and_then(|s| toml::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Saves the metadata, removing the file if there's nothing in it.
+    pub fn save_meta(&self, meta: &CategoryMeta) -> Result<()> {
+        if !self.exists() {
+            bail!("category {} doesn't exist", self.print_full());
+        }
+
+        let path = self.meta_path();
+        if meta == &CategoryMeta::default() {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        } else {
+            fs::write(path, toml::to_string_pretty(meta)?)?;
+        }
+        Ok(())
+    }
+
+    /// This category and its parents, starting at the top level category.
+    fn lineage(&self) -> Vec<Self> {
+        let mut lineage = vec![self.clone()];
+        while let Some(parent) = lineage.last().unwrap().parent() {
+            lineage.push(parent);
+        }
+        lineage.reverse();
+        lineage
+    }
+
+    /// Tags given to new cards in this category, including those of the parent categories.
+    /// Subcategories override the values of their parents.
+    pub fn default_tags(&self) -> BTreeMap<String, String> {
+        let mut tags = BTreeMap::new();
+        for category in self.lineage() {
+            tags.extend(category.meta().default_tags);
+        }
+        tags
+    }
+
+    /// The scheduler settings for cards in this category, unset fields are taken from the parent categories.
+    pub fn scheduler(&self) -> SchedulerOverrides {
+        let mut settings = SchedulerOverrides::default();
+        for category in self.lineage() {
+            if let Some(overrides) = category.meta().scheduler {
+                settings = overrides.or(settings);
+            }
+        }
+        settings
+    }
+
+    /// The target recall of the scheduler settings, or the default one.
+    pub fn target_recall(&self) -> f32 {
+        self.scheduler()
+            .target_recall
+            .unwrap_or(crate::recall_rate::DEFAULT_TARGET_RECALL)
+    }
+
+    /// Stats for the cards in this category and its subcategories.
+    pub fn stats(&self) -> CategoryStats {
+        let cards: Vec<Card<AnyType>> = Card::load_all_cards()
+            .into_iter()
+            .filter(|card| card.category().is_within(self))
+            .collect();
+
+        let recall_rates: Vec<f32> = cards.iter().filter_map(|card| card.recall_rate()).collect();
+        let average_recall = (!recall_rates.is_empty())
+            .then(|| recall_rates.iter().sum::<f32>() / recall_rates.len() as f32);
+
+        let subcategories = walkdir::WalkDir::new(self.as_path())
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|e| Self::is_visible_dir(e))
+            .filter_map(Result::ok)
+            .count();
+
+        CategoryStats {
+            cards: cards.len(),
+            pending: cards.iter().filter(|card| card.is_pending()).count(),
+            suspended: cards.iter().filter(|card| card.is_suspended()).count(),
+            unfinished: cards.iter().filter(|card| !card.is_finished()).count(),
+            average_recall,
+            subcategories,
+        }
+    }
+}
+
+pub(crate) fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        bail!("invalid category name: '{}'", name);
+    }
+    Ok(())
+}
+
+/// Settings for a category, stored in a file in its directory.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CategoryMeta {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// Tags that new cards in the category get.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub default_tags: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduler: Option<SchedulerOverrides>,
+}
+
+/// Scheduling settings for the cards in a category, unset fields fall back to the parent category.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SchedulerOverrides {
+    /// Recall rate below which a card is due for review.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_recall: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_cards_per_day: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_interval_days: Option<u32>,
+}
+
+impl SchedulerOverrides {
+    /// Fields that are unset here are taken from `other`.
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            target_recall: self.target_recall.or(other.target_recall),
+            new_cards_per_day: self.new_cards_per_day.or(other.new_cards_per_day),
+            max_interval_days: self.max_interval_days.or(other.max_interval_days),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CategoryStats {
+    pub cards: usize,
+    pub pending: usize,
+    pub suspended: usize,
+    pub unfinished: usize,
+    /// Average recall rate of the cards that have been reviewed.
+    pub average_recall: Option<f32>,
+    /// Number of subcategories at any depth.
+    pub subcategories: usize,
 }
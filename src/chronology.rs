@@ -0,0 +1,156 @@
+use crate::card::{AnyType, BackSide, EventCard};
+use crate::common::CardId;
+use crate::Card;
+use timestamped::TimeStamp;
+
+/// Loads all event cards, sorted by when they started.
+/// Events starting at the same time are sorted by when they ended.
+pub fn chronology() -> Vec<(CardId, EventCard)> {
+    let mut events: Vec<(CardId, EventCard)> = Card::load_all_cards()
+        .into_iter()
+        .filter_map(|card| match card.card_type() {
+            AnyType::Event(event) => Some((card.id(), event.clone())),
+            _ => None,
+        })
+        .collect();
+
+    events.sort_by(|(_, a), (_, b)| {
+        a.start_time
+            .cmp(&b.start_time)
+            .then_with(|| a.end().cmp(b.end()))
+    });
+
+    events
+}
+
+/// Events that happened, at least partly, between `from` and `to`.
+pub fn events_between(from: &TimeStamp, to: &TimeStamp) -> Vec<CardId> {
+    chronology()
+        .into_iter()
+        .filter(|(_, event)| &event.start_time <= to && from <= event.end())
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Events that were ongoing at the given time.
+pub fn events_at(time: &TimeStamp) -> Vec<CardId> {
+    chronology()
+        .into_iter()
+        .filter(|(_, event)| event.contains_time(time))
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Other events overlapping in time with the given event.
+pub fn overlapping(id: CardId) -> Vec<CardId> {
+    related(id, |this, other| this.overlaps(other))
+}
+
+/// Events that happened entirely within the given event.
+pub fn contained_in(id: CardId) -> Vec<CardId> {
+    related(id, |this, other| this.contains(other))
+}
+
+/// Events that the given event happened entirely within.
+pub fn containing(id: CardId) -> Vec<CardId> {
+    related(id, |this, other| other.contains(this))
+}
+
+fn related(id: CardId, f: impl Fn(&EventCard, &EventCard) -> bool) -> Vec<CardId> {
+    let events = chronology();
+    let Some((_, this)) = events.iter().find(|(event_id, _)| *event_id == id) else {
+        return vec![];
+    };
+
+    events
+        .iter()
+        .filter(|(other_id, other)| *other_id != id && f(this, other))
+        .map(|(other_id, _)| *other_id)
+        .collect()
+}
+
+/// A review item derived from event cards, these are generated on the fly and not saved.
+#[derive(Debug, Clone)]
+pub enum EventQuiz {
+    /// Which of the two events happened first?
+    WhichFirst {
+        first: CardId,
+        second: CardId,
+        answer: CardId,
+    },
+    /// When did the event happen?
+    When { event: CardId, answer: TimeStamp },
+}
+
+impl EventQuiz {
+    pub fn question(&self) -> String {
+        match self {
+            EventQuiz::WhichFirst { first, second, .. } => format!(
+                "What came first: {} or {}?",
+                Card::from_id(*first).unwrap().print(),
+                Card::from_id(*second).unwrap().print()
+            ),
+            EventQuiz::When { event, .. } => {
+                format!(
+                    "When did this happen: {}?",
+                    Card::from_id(*event).unwrap().print()
+                )
+            }
+        }
+    }
+
+    pub fn back_side(&self) -> BackSide {
+        match self {
+            EventQuiz::WhichFirst { answer, .. } => BackSide::Card(*answer),
+            EventQuiz::When { answer, .. } => BackSide::Time(answer.clone()),
+        }
+    }
+
+    /// The event cards this quiz item was generated from.
+    pub fn events(&self) -> Vec<CardId> {
+        match self {
+            EventQuiz::WhichFirst { first, second, .. } => vec![*first, *second],
+            EventQuiz::When { event, .. } => vec![*event],
+        }
+    }
+}
+
+/// Generates quiz items from all event cards.
+///
+/// "What came first" items are only made from neighbouring events in the chronology that don't
+/// overlap, so that the answer is unambiguous.
+pub fn quiz_items() -> Vec<EventQuiz> {
+    let events = chronology();
+    let mut items = vec![];
+
+    for (id, event) in &events {
+        items.push(EventQuiz::When {
+            event: *id,
+            answer: event.start_time.clone(),
+        });
+    }
+
+    for pair in events.windows(2) {
+        let (earlier_id, earlier) = &pair[0];
+        let (later_id, later) = &pair[1];
+
+        if earlier.overlaps(later) {
+            continue;
+        }
+
+        // Order by id so the earlier event isn't always presented first.
+        let (first, second) = if earlier_id < later_id {
+            (*earlier_id, *later_id)
+        } else {
+            (*later_id, *earlier_id)
+        };
+
+        items.push(EventQuiz::WhichFirst {
+            first,
+            second,
+            answer: *earlier_id,
+        });
+    }
+
+    items
+}
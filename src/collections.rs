@@ -5,7 +5,7 @@ use std::{
 };
 
 use git2::{
-    Cred, FetchOptions, IndexAddOption, PushOptions, RemoteCallbacks, Repository, Signature,
+    Commit, Cred, FetchOptions, IndexAddOption, PushOptions, RemoteCallbacks, Repository, Signature,
 };
 
 use crate::{categories::Category, github::LoginInfo, paths::get_collections_path};
@@ -180,6 +180,36 @@ pub fn commit(repo: &Repository) -> Result<(), git2::Error> {
     Ok(())
 }
 
+/// Stages the changes to the given paths, removed files too, and commits them.
+/// Paths are relative to the repo and can be directories. Works on repos without any commits yet.
+pub fn commit_paths(
+    repo: &Repository,
+    paths: &[PathBuf],
+    message: &str,
+) -> Result<(), git2::Error> {
+    let pathspecs: Vec<String> = paths
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+
+    let mut index = repo.index()?;
+    index.add_all(pathspecs.iter(), IndexAddOption::DEFAULT, None)?;
+    index.update_all(pathspecs.iter(), None)?;
+    index.write()?;
+
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(_) => None,
+    };
+    let parents: Vec<&Commit> = parent.iter().collect();
+
+    let sig = Signature::now("robot", "robot@example.com")?;
+    repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)?;
+
+    Ok(())
+}
+
 pub fn push(repo: &Repository) -> Result<(), git2::Error> {
     let mut remote = repo.find_remote("origin")?;
 
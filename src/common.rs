@@ -6,6 +6,7 @@ use std::process::Command;
 use std::str::FromStr;
 use std::time::SystemTime;
 use std::time::{Duration, UNIX_EPOCH};
+use timestamped::TimeStamp;
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Ord, Eq, PartialEq, PartialOrd, Copy, Hash)]
@@ -98,3 +99,26 @@ pub fn get_last_modified(path: &Path) -> Duration {
         .unwrap();
     Duration::from_secs(secs)
 }
+
+/// Approximate position of a timestamp in years, for comparing and plotting dates.
+///
+/// Reads the leading year/month/day numbers from the serialized form, a leading `-` marks years BC.
+pub fn timestamp_as_years(time: &TimeStamp) -> Option<f32> {
+    let s = time.serialize();
+    let s = s.trim();
+    let (sign, s) = match s.strip_prefix('-') {
+        Some(rest) => (-1., rest),
+        None => (1., s),
+    };
+
+    let mut parts = s
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<f32>());
+
+    let year = parts.next()?.ok()?;
+    let month = parts.next().and_then(Result::ok).unwrap_or(1.);
+    let day = parts.next().and_then(Result::ok).unwrap_or(1.);
+
+    Some(sign * year + (month - 1.) / 12. + (day - 1.) / 365.)
+}
@@ -0,0 +1,266 @@
+use crate::collections::Collection;
+use crate::common::CardId;
+use crate::filter::{validate_filter, FilterContext, SortKey};
+use crate::get_containing_file_paths;
+use crate::paths::get_decks_path;
+use eyre::{bail, Result};
+use sanitize_filename::sanitize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+/// Decks are saved in this hidden directory of a collection so they sync along with the cards.
+pub const COLLECTION_DECKS_DIR: &str = ".decks";
+
+/// Extension of deck files, so they're not loaded as cards.
+pub const DECK_EXTENSION: &str = "deck";
+
+/// A saved filter with a name, stored in a collection so it syncs along with the cards.
+///
+/// Decks can be built from other decks, e.g. a deck with the filter `recall < 0.8`
+/// that includes `german` and excludes `german verbs`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Deck {
+    pub name: String,
+    /// Samsvar filter expression, an empty filter matches every card.
+    #[serde(default)]
+    pub filter: String,
+    /// Only cards that are also in all of these decks.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Leave out cards that are in any of these decks.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sort: Vec<SortKey>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// A deck with how many of its cards are in each state.
+#[derive(Debug, Clone)]
+pub struct DeckSummary {
+    pub name: String,
+    pub total: usize,
+    /// Reviewed cards whose recall has dropped below the target of their category.
+    pub due: usize,
+    /// Cards that haven't been reviewed yet.
+    pub new: usize,
+    pub suspended: usize,
+}
+
+impl Deck {
+    pub fn new(name: impl Into<String>, filter: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            filter: filter.into(),
+            ..Default::default()
+        }
+    }
+
+    /// The file of the deck in whichever collection it's stored in.
+    fn find(name: &str) -> Option<PathBuf> {
+        deck_files()
+            .into_iter()
+            .find(|path| path.file_stem().is_some_and(|stem| stem == name))
+    }
+
+    /// Decks used to be saved in the share directory, which isn't synced.
+    fn legacy_path(name: &str) -> PathBuf {
+        get_decks_path().join(format!("{}.toml", name))
+    }
+
+    pub fn load(name: &str) -> Option<Self> {
+        let path = Self::find(name).unwrap_or_else(|| Self::legacy_path(name));
+        let s = fs::read_to_string(path).ok()?;
+        toml::from_str(&s).ok()
+    }
+
+    pub fn load_all() -> Vec<Self> {
+        let legacy = get_containing_file_paths(&get_decks_path(), Some("toml"));
+        let mut decks: Vec<Self> = deck_files()
+            .into_iter()
+            .chain(legacy)
+            .filter_map(|path| {
+                let s = fs::read_to_string(path).ok()?;
+                toml::from_str(&s).ok()
+            })
+            .collect();
+        // Stable, so a deck in a collection wins over an old copy in the share directory.
+        decks.sort_by(|a, b| a.name.cmp(&b.name));
+        decks.dedup_by(|a, b| a.name == b.name);
+        decks
+    }
+
+    /// Saves the deck, failing if its filter is invalid or it refers to decks that don't exist
+    /// or back to itself.
+    pub fn save(&self) -> Result<()> {
+        if self.name.trim().is_empty() || sanitize(&self.name) != self.name {
+            bail!("invalid deck name: '{}'", self.name);
+        }
+
+        if !self.filter.trim().is_empty() {
+            validate_filter(&self.filter)?;
+        }
+
+        for name in self.include.iter().chain(&self.exclude) {
+            if name != &self.name && Self::load(name).is_none() {
+                bail!("deck '{}' doesn't exist", name);
+            }
+        }
+        self.matching(&FilterContext::default(), Some(self), &mut vec![])?;
+
+        let path = Self::find(&self.name).unwrap_or_else(|| {
+            collection_decks_path(&Collection::default())
+                .join(format!("{}.{}", self.name, DECK_EXTENSION))
+        });
+        fs::write(path, toml::to_string_pretty(self)?)?;
+
+        let legacy_path = Self::legacy_path(&self.name);
+        if legacy_path.exists() {
+            fs::remove_file(legacy_path)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the deck, failing if other decks are built from it.
+    pub fn delete(name: &str) -> Result<()> {
+        let users: Vec<String> = Self::load_all()
+            .into_iter()
+            .filter(|deck| deck.include.iter().chain(&deck.exclude).any(|n| n == name))
+            .map(|deck| deck.name)
+            .collect();
+        if !users.is_empty() {
+            bail!(
+                "can't delete deck '{}', it's used by: {}",
+                name,
+                users.join(", ")
+            );
+        }
+
+        let paths: Vec<PathBuf> = Self::find(name)
+            .into_iter()
+            .chain(Some(Self::legacy_path(name)))
+            .filter(|path| path.exists())
+            .collect();
+        if paths.is_empty() {
+            bail!("deck '{}' doesn't exist", name);
+        }
+        for path in paths {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// The cards in the deck, sorted and limited.
+    pub fn cards(&self, ctx: &FilterContext) -> Result<Vec<CardId>> {
+        let mut cards: Vec<CardId> = self.matching(ctx, None, &mut vec![])?.into_iter().collect();
+        ctx.sort(&mut cards, &self.sort);
+        if let Some(limit) = self.limit {
+            cards.truncate(limit);
+        }
+        Ok(cards)
+    }
+
+    /// The cards matching the deck before sorting and limiting, which is what other decks build on.
+    /// `unsaved` is used instead of the saved deck with the same name, wherever it's referred to.
+    /// `visiting` holds the decks being resolved, to catch decks that include themselves.
+    fn matching(
+        &self,
+        ctx: &FilterContext,
+        unsaved: Option<&Self>,
+        visiting: &mut Vec<String>,
+    ) -> Result<BTreeSet<CardId>> {
+        if visiting.contains(&self.name) {
+            visiting.push(self.name.clone());
+            bail!("decks refer to each other: {}", visiting.join(" -> "));
+        }
+        visiting.push(self.name.clone());
+
+        let mut cards: BTreeSet<CardId> = if self.filter.trim().is_empty() {
+            ctx.cards().map(|card| card.id()).collect()
+        } else {
+            ctx.filter(&self.filter).into_iter().collect()
+        };
+
+        for name in &self.include {
+            let deck = self.resolve(name, unsaved)?;
+            let included = deck.matching(ctx, unsaved, visiting)?;
+            cards.retain(|id| included.contains(id));
+        }
+
+        for name in &self.exclude {
+            let deck = self.resolve(name, unsaved)?;
+            let excluded = deck.matching(ctx, unsaved, visiting)?;
+            cards.retain(|id| !excluded.contains(id));
+        }
+
+        visiting.pop();
+        Ok(cards)
+    }
+
+    /// Loads a deck this one refers to, or `unsaved` if it has that name.
+    fn resolve(&self, name: &str, unsaved: Option<&Self>) -> Result<Self> {
+        if let Some(deck) = unsaved.filter(|deck| deck.name == name) {
+            return Ok(deck.clone());
+        }
+        match Self::load(name) {
+            Some(deck) => Ok(deck),
+            None => bail!("deck '{}' refers to missing deck '{}'", self.name, name),
+        }
+    }
+
+    pub fn summary(&self, ctx: &FilterContext) -> Result<DeckSummary> {
+        let mut summary = DeckSummary {
+            name: self.name.clone(),
+            total: 0,
+            due: 0,
+            new: 0,
+            suspended: 0,
+        };
+
+        for id in self.cards(ctx)? {
+            let Some(card) = ctx.card(id) else {
+                continue;
+            };
+
+            summary.total += 1;
+            if card.is_suspended() {
+                summary.suspended += 1;
+            } else if card.is_pending() {
+                summary.new += 1;
+            } else if ctx.due_in(card).is_some_and(|days| days <= 0.) {
+                summary.due += 1;
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+/// All saved decks with their current counts, decks that can't be resolved are left out.
+pub fn list() -> Vec<DeckSummary> {
+    let ctx = FilterContext::load();
+    Deck::load_all()
+        .iter()
+        .filter_map(|deck| deck.summary(&ctx).ok())
+        .collect()
+}
+
+pub fn collection_decks_path(collection: &Collection) -> PathBuf {
+    let path = collection.path().join(COLLECTION_DECKS_DIR);
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn deck_files() -> Vec<PathBuf> {
+    let mut files = vec![];
+    for col in Collection::load_all() {
+        let dir = col.path().join(COLLECTION_DECKS_DIR);
+        if dir.exists() {
+            files.extend(get_containing_file_paths(&dir, Some(DECK_EXTENSION)));
+        }
+    }
+    files
+}
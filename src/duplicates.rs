@@ -0,0 +1,201 @@
+use crate::answer::{levenshtein, normalize};
+use crate::attribute::AttributeId;
+use crate::card::AnyType;
+use crate::common::CardId;
+use crate::render::to_plain_text;
+use crate::Card;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// How similar two fronts must be, from 0 to 1, to count as near-duplicates.
+pub const DEFAULT_THRESHOLD: f32 = 0.8;
+
+/// Words in more fronts than this, like "what" or "the", are too common to pick out
+/// which fronts are worth comparing.
+const MAX_CANDIDATE_FREQUENCY: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKind {
+    /// The fronts are the same after normalizing.
+    SameFront,
+    /// The fronts are similar enough that they likely ask the same thing.
+    SimilarFront,
+    /// Instances with the same name in the same class.
+    SameInstance { class: CardId },
+    /// More than one card for the same attribute of the same instance.
+    SameAttribute {
+        attribute: AttributeId,
+        instance: CardId,
+    },
+}
+
+/// Cards that look like duplicates of each other, to merge or delete all but one.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    pub kind: DuplicateKind,
+    pub cards: Vec<CardId>,
+    /// The lowest similarity of the pairs that were grouped together, 1.0 for exact duplicates.
+    pub similarity: f32,
+}
+
+pub fn find_duplicates(threshold: f32) -> Vec<Cluster> {
+    find_in(&Card::load_all_cards(), threshold)
+}
+
+pub fn find_in(cards: &[Card<AnyType>], threshold: f32) -> Vec<Cluster> {
+    let mut fronts: BTreeMap<String, Vec<CardId>> = BTreeMap::new();
+    let mut instances: BTreeMap<(CardId, String), Vec<CardId>> = BTreeMap::new();
+    let mut attribute_cards: BTreeMap<(AttributeId, CardId), Vec<CardId>> = BTreeMap::new();
+
+    for card in cards {
+        match card.card_type() {
+            AnyType::Instance(instance) => instances
+                .entry((instance.class, normalize(&instance.name)))
+                .or_default()
+                .push(card.id()),
+            AnyType::Attribute(attr) => attribute_cards
+                .entry((attr.attribute, attr.instance))
+                .or_default()
+                .push(card.id()),
+            _ => {
+                let front = normalize(&to_plain_text(&card.print()));
+                if !front.is_empty() {
+                    fronts.entry(front).or_default().push(card.id());
+                }
+            }
+        }
+    }
+
+    let mut clusters = vec![];
+
+    for ((attribute, instance), ids) in attribute_cards {
+        if ids.len() > 1 {
+            clusters.push(Cluster {
+                kind: DuplicateKind::SameAttribute {
+                    attribute,
+                    instance,
+                },
+                cards: ids,
+                similarity: 1.0,
+            });
+        }
+    }
+
+    for ((class, _), ids) in instances {
+        if ids.len() > 1 {
+            clusters.push(Cluster {
+                kind: DuplicateKind::SameInstance { class },
+                cards: ids,
+                similarity: 1.0,
+            });
+        }
+    }
+
+    for ids in fronts.values() {
+        if ids.len() > 1 {
+            clusters.push(Cluster {
+                kind: DuplicateKind::SameFront,
+                cards: ids.clone(),
+                similarity: 1.0,
+            });
+        }
+    }
+
+    clusters.extend(similar_fronts(&fronts, threshold));
+    clusters
+}
+
+/// Similarity of two normalized texts from 0 to 1, the mean of the word overlap
+/// and the edit distance relative to the length.
+///
+/// Both have to be high, questions from the same template like "what is the capital of france"
+/// and "... of germany" are close in edit distance but differ in a word that matters.
+pub fn similarity(a: &str, b: &str) -> f32 {
+    if a == b {
+        return 1.0;
+    }
+
+    let a_words: BTreeSet<&str> = a.split_whitespace().collect();
+    let b_words: BTreeSet<&str> = b.split_whitespace().collect();
+    let union = a_words.union(&b_words).count();
+    let words = if union == 0 {
+        0.0
+    } else {
+        a_words.intersection(&b_words).count() as f32 / union as f32
+    };
+
+    let longest = a.chars().count().max(b.chars().count());
+    let chars = 1.0 - levenshtein(a, b) as f32 / longest as f32;
+
+    (words + chars) / 2.0
+}
+
+/// Groups fronts that are similar to each other, directly or through other fronts.
+/// Only fronts sharing an uncommon word are compared, so unrelated cards are skipped cheaply.
+///
+/// Cards with the same front are already in a [`DuplicateKind::SameFront`] cluster,
+/// so only one of them is put in the group.
+fn similar_fronts(fronts: &BTreeMap<String, Vec<CardId>>, threshold: f32) -> Vec<Cluster> {
+    let texts: Vec<&String> = fronts.keys().collect();
+
+    let mut by_word: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, text) in texts.iter().enumerate() {
+        let words: BTreeSet<&str> = text.split_whitespace().collect();
+        for word in words {
+            by_word.entry(word).or_default().push(i);
+        }
+    }
+    by_word.retain(|_, texts| texts.len() <= MAX_CANDIDATE_FREQUENCY);
+
+    let mut parents: Vec<usize> = (0..texts.len()).collect();
+    let mut pairs = vec![];
+
+    for (i, text) in texts.iter().enumerate() {
+        let candidates: BTreeSet<usize> = text
+            .split_whitespace()
+            .filter_map(|word| by_word.get(word))
+            .flat_map(|texts| texts.iter().copied())
+            .filter(|j| *j > i)
+            .collect();
+
+        for j in candidates {
+            let sim = similarity(text, texts[j]);
+            if sim >= threshold {
+                let (a, b) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                parents[a] = b;
+                pairs.push((i, sim));
+            }
+        }
+    }
+
+    let mut lowest: BTreeMap<usize, f32> = BTreeMap::new();
+    for (i, sim) in pairs {
+        let root = find_root(&mut parents, i);
+        let entry = lowest.entry(root).or_insert(sim);
+        *entry = entry.min(sim);
+    }
+
+    let mut groups: BTreeMap<usize, Vec<CardId>> = BTreeMap::new();
+    for (i, text) in texts.iter().enumerate() {
+        let root = find_root(&mut parents, i);
+        if lowest.contains_key(&root) {
+            groups.entry(root).or_default().push(fronts[*text][0]);
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(root, cards)| Cluster {
+            kind: DuplicateKind::SimilarFront,
+            cards,
+            similarity: lowest[&root],
+        })
+        .collect()
+}
+
+fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
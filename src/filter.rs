@@ -0,0 +1,301 @@
+use crate::card::AnyType;
+use crate::categories::Category;
+use crate::common::CardId;
+use crate::graph::DependencyGraph;
+use crate::hierarchy::ClassHierarchy;
+use crate::Card;
+use rayon::prelude::*;
+use samsvar::{json, Matcher, Value};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+/// What the filter keys that look at other cards need, computed once for all cards.
+///
+/// Evaluating keys like `dependents` or `minrecrecall` card by card loads the other cards
+/// again for each one, with this the whole library is filtered in one pass.
+pub struct FilterContext {
+    cards: BTreeMap<CardId, Card<AnyType>>,
+    graph: DependencyGraph,
+    hierarchy: ClassHierarchy,
+    dependents: BTreeMap<CardId, usize>,
+    recall: BTreeMap<CardId, f32>,
+    finished: BTreeMap<CardId, bool>,
+    /// Target recall of each category, which is read from the category files.
+    target_recall: BTreeMap<Category, f32>,
+    /// Maturity integrates the recall curve, so it's only computed if a filter asks for it.
+    maturity: OnceLock<BTreeMap<CardId, f32>>,
+}
+
+impl Default for FilterContext {
+    /// A context without any cards.
+    fn default() -> Self {
+        Self::new(vec![])
+    }
+}
+
+impl FilterContext {
+    pub fn load() -> Self {
+        Self::new(Card::load_all_cards())
+    }
+
+    pub fn new(cards: Vec<Card<AnyType>>) -> Self {
+        let graph = DependencyGraph::from_cards(&cards);
+        let hierarchy = ClassHierarchy::from_cards(&cards);
+
+        let mut dependents: BTreeMap<CardId, usize> = BTreeMap::new();
+        for card in &cards {
+            for dep in card.dependency_ids() {
+                *dependents.entry(dep).or_default() += 1;
+            }
+        }
+
+        let recall = cards
+            .par_iter()
+            .map(|card| (card.id(), card.recall_rate().unwrap_or_default()))
+            .collect();
+
+        let finished = cards
+            .par_iter()
+            .map(|card| (card.id(), card.is_finished()))
+            .collect();
+
+        let mut target_recall = BTreeMap::new();
+        for card in &cards {
+            if !target_recall.contains_key(card.category()) {
+                let category = card.category().clone();
+                let target = category.target_recall();
+                target_recall.insert(category, target);
+            }
+        }
+
+        Self {
+            cards: cards.into_iter().map(|card| (card.id(), card)).collect(),
+            graph,
+            hierarchy,
+            dependents,
+            recall,
+            finished,
+            target_recall,
+            maturity: OnceLock::new(),
+        }
+    }
+
+    pub fn card(&self, id: CardId) -> Option<&Card<AnyType>> {
+        self.cards.get(&id)
+    }
+
+    pub fn cards(&self) -> impl Iterator<Item = &Card<AnyType>> {
+        self.cards.values()
+    }
+
+    fn maturity(&self, id: CardId) -> f32 {
+        let maturity = self.maturity.get_or_init(|| {
+            self.cards
+                .par_iter()
+                .map(|(id, card)| (*id, card.maturity()))
+                .collect()
+        });
+        maturity.get(&id).copied().unwrap_or_default()
+    }
+
+    /// Same as [`Card::due_in`] without reading the category settings for each card.
+    pub fn due_in(&self, card: &Card<AnyType>) -> Option<f32> {
+        let target = match self.target_recall.get(card.category()) {
+            Some(target) => *target,
+            None => card.category().target_recall(),
+        };
+        card.due_in_with_target(target)
+    }
+
+    /// Existing cards among the direct and indirect dependencies.
+    fn all_dependencies(&self, id: CardId) -> Vec<CardId> {
+        self.graph
+            .all_dependencies(id)
+            .into_iter()
+            .filter(|dep| self.cards.contains_key(dep))
+            .collect()
+    }
+
+    pub fn matches(&self, card: &Card<AnyType>, filter: &str) -> bool {
+        FilterCard { card, ctx: self }.eval(filter.to_string())
+    }
+
+    /// The cards matching the filter.
+    pub fn matching(&self, filter: &str) -> Vec<&Card<AnyType>> {
+        self.cards
+            .par_iter()
+            .filter(|(_, card)| self.matches(card, filter))
+            .map(|(_, card)| card)
+            .collect()
+    }
+
+    pub fn filter(&self, filter: &str) -> Vec<CardId> {
+        self.matching(filter)
+            .into_iter()
+            .map(|card| card.id())
+            .collect()
+    }
+
+    /// The value of a filter key for the card.
+    pub fn value(&self, card: &Card<AnyType>, key: &str) -> Option<Value> {
+        FilterCard { card, ctx: self }.get_val(key)
+    }
+
+    /// Sorts by the first key, then the next key for cards that are equal, and so on.
+    /// Cards without a value for a key come first in ascending order.
+    pub fn sort(&self, ids: &mut [CardId], keys: &[SortKey]) {
+        if keys.is_empty() {
+            return;
+        }
+
+        let values: BTreeMap<CardId, Vec<Option<Value>>> = ids
+            .par_iter()
+            .map(|id| {
+                let values = keys
+                    .iter()
+                    .map(|key| self.card(*id).and_then(|card| self.value(card, &key.key)))
+                    .collect();
+                (*id, values)
+            })
+            .collect();
+
+        ids.sort_by(|a, b| {
+            for (i, key) in keys.iter().enumerate() {
+                let ordering = compare_values(&values[a][i], &values[b][i]);
+                let ordering = if key.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+    }
+}
+
+/// A filter key to order cards by.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub key: String,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+impl SortKey {
+    pub fn ascending(key: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            descending: false,
+        }
+    }
+
+    pub fn descending(key: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            descending: true,
+        }
+    }
+}
+
+/// Numbers, strings and bools compare as expected, values of different kinds by kind.
+fn compare_values(a: &Option<Value>, b: &Option<Value>) -> Ordering {
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Object(_) => 5,
+        }
+    }
+
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Less,
+        (Some(_), None) => Ordering::Greater,
+        (Some(a), Some(b)) => match (a, b) {
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Number(a), Value::Number(b)) => a
+                .as_f64()
+                .unwrap_or_default()
+                .total_cmp(&b.as_f64().unwrap_or_default()),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            _ => rank(a).cmp(&rank(b)),
+        },
+    }
+}
+
+/// Checks that the filter can be evaluated, for filters that are saved to be used later.
+///
+/// The filter is evaluated once against a card without any values, samsvar panics on
+/// expressions it can't parse so the panic is turned into an error.
+pub fn validate_filter(filter: &str) -> eyre::Result<()> {
+    struct NoValues;
+
+    impl Matcher for NoValues {
+        fn get_val(&self, _key: &str) -> Option<Value> {
+            None
+        }
+    }
+
+    let input = filter.to_string();
+    match std::panic::catch_unwind(move || NoValues.eval(input)) {
+        Ok(_) => Ok(()),
+        Err(_) => eyre::bail!("invalid filter: '{}'", filter),
+    }
+}
+
+/// A card evaluated against a [`FilterContext`], keys that don't need other cards
+/// are looked up on the card itself.
+pub struct FilterCard<'a> {
+    pub card: &'a Card<AnyType>,
+    pub ctx: &'a FilterContext,
+}
+
+impl Matcher for FilterCard<'_> {
+    fn get_val(&self, key: &str) -> Option<samsvar::Value> {
+        let ctx = self.ctx;
+        let id = self.card.id();
+
+        match key {
+            "recall" => json!(ctx.recall.get(&id).copied().unwrap_or_default()),
+            "stability" => json!(ctx.maturity(id)),
+            "resolved" => json!(ctx.all_dependencies(id).iter().all(|dep| ctx.finished[dep])),
+            "minrecrecall" => {
+                let min = ctx
+                    .all_dependencies(id)
+                    .iter()
+                    .map(|dep| (ctx.recall[dep] * 1000.) as usize)
+                    .min()
+                    .unwrap_or(usize::MAX);
+                json!(min as f32 / 1000.)
+            }
+            "minrecstab" => {
+                let min = ctx
+                    .all_dependencies(id)
+                    .iter()
+                    .map(|dep| (ctx.maturity(*dep) * 1000.) as usize)
+                    .min()
+                    .unwrap_or(usize::MAX);
+                json!(min as f32 / 1000.)
+            }
+            "dependents" => json!(ctx.dependents.get(&id).copied().unwrap_or_default()),
+            "due_in" => json!(ctx.due_in(self.card)),
+            key if key.starts_with("ancestor_class:") => {
+                let class = &key["ancestor_class:".len()..];
+                json!(ctx.hierarchy.ancestors(id).into_iter().any(|ancestor| {
+                    ancestor.to_string() == class
+                        || ctx.card(ancestor).is_some_and(|card| card.print() == class)
+                }))
+            }
+            _ => return self.card.get_val(key),
+        }
+        .into()
+    }
+}
@@ -0,0 +1,364 @@
+use crate::card::{AnyType, BackSide, ClassCard, UnfinishedCard};
+use crate::common::CardId;
+use crate::graph::{describe_cycle, DependencyGraph};
+use crate::paths::{
+    get_cards_path, get_collections_path, get_review_details_path, get_review_path,
+};
+use crate::{get_containing_file_paths, Card};
+use eyre::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use walkdir::WalkDir;
+
+#[derive(Debug, Clone)]
+pub enum Problem {
+    /// A file in the cards or collections directory that can't be loaded as a card.
+    InvalidFile { path: PathBuf, error: String },
+    /// A file in an attributes directory that can't be loaded as an attribute.
+    InvalidAttributeFile { path: PathBuf, error: String },
+    /// The same card id is stored in more than one file.
+    DuplicateId { id: CardId, paths: Vec<PathBuf> },
+    /// Reviews or review details saved for a card that doesn't exist.
+    OrphanedReviews { id: CardId, path: PathBuf },
+    /// The card depends on a card that doesn't exist.
+    DanglingDependency { card: CardId, dependency: CardId },
+    /// The back side of the card refers to a card that doesn't exist.
+    MissingReference { card: CardId, reference: CardId },
+    /// The class of an instance, or the parent of a class, is missing or not a class.
+    NotAClass { card: CardId, class: CardId },
+    /// An attribute card whose instance doesn't exist.
+    MissingInstance { card: CardId, instance: CardId },
+    /// Cards that depend on each other in a loop.
+    Cycle(Vec<CardId>),
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::InvalidFile { path, error } => {
+                write!(f, "invalid card file {}: {}", path.display(), error)
+            }
+            Problem::InvalidAttributeFile { path, error } => {
+                write!(f, "invalid attribute file {}: {}", path.display(), error)
+            }
+            Problem::DuplicateId { id, paths } => write!(
+                f,
+                "card id {} is used by several files: {}",
+                id,
+                paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Problem::OrphanedReviews { id, .. } => write!(f, "reviews for missing card {}", id),
+            Problem::DanglingDependency { card, dependency } => {
+                write!(f, "card {} depends on missing card {}", card, dependency)
+            }
+            Problem::MissingReference { card, reference } => {
+                write!(
+                    f,
+                    "back side of card {} refers to missing card {}",
+                    card, reference
+                )
+            }
+            Problem::NotAClass { card, class } => {
+                write!(
+                    f,
+                    "class {} of card {} is missing or not a class",
+                    class, card
+                )
+            }
+            Problem::MissingInstance { card, instance } => {
+                write!(
+                    f,
+                    "attribute card {} has missing instance {}",
+                    card, instance
+                )
+            }
+            Problem::Cycle(cycle) => write!(f, "dependency cycle: {}", describe_cycle(cycle)),
+        }
+    }
+}
+
+impl Problem {
+    pub fn is_repairable(&self) -> bool {
+        match self {
+            Problem::InvalidFile { .. }
+            | Problem::InvalidAttributeFile { .. }
+            | Problem::DuplicateId { .. }
+            | Problem::MissingInstance { .. } => false,
+            Problem::Cycle(cycle) => cycle_edge_to_remove(cycle).is_some(),
+            _ => true,
+        }
+    }
+
+    /// Fixes the problem if it can be done without losing anything important,
+    /// returns false if it has to be fixed by hand.
+    pub fn repair(&self) -> Result<bool> {
+        match self {
+            Problem::InvalidFile { .. }
+            | Problem::InvalidAttributeFile { .. }
+            | Problem::DuplicateId { .. }
+            | Problem::MissingInstance { .. } => return Ok(false),
+            Problem::OrphanedReviews { id, .. } => {
+                for dir in [get_review_path(), get_review_details_path()] {
+                    let path = dir.join(id.to_string());
+                    if path.exists() {
+                        fs::remove_file(path)?;
+                    }
+                }
+            }
+            Problem::DanglingDependency { card, dependency } => {
+                let Some(mut card) = Card::from_id(*card) else {
+                    return Ok(false);
+                };
+                card.rm_dependency(*dependency);
+            }
+            Problem::MissingReference { card, reference } => {
+                let Some(mut card) = Card::from_id(*card) else {
+                    return Ok(false);
+                };
+                let back = match card.back_side() {
+                    Some(BackSide::List(ids)) => {
+                        let ids = ids.iter().filter(|id| *id != reference).copied();
+                        BackSide::List(ids.collect())
+                    }
+                    _ => BackSide::default(),
+                };
+                card.set_back_side(back)?;
+            }
+            Problem::NotAClass { card, .. } => {
+                let Some(card) = Card::from_id(*card) else {
+                    return Ok(false);
+                };
+                match card.card_type().clone() {
+                    AnyType::Instance(instance) => {
+                        card.into_type(UnfinishedCard {
+                            front: instance.name,
+                        });
+                    }
+                    AnyType::Class(class) => {
+                        card.into_type(ClassCard {
+                            parent_class: None,
+                            ..class
+                        });
+                    }
+                    _ => return Ok(false),
+                }
+            }
+            Problem::Cycle(cycle) => {
+                let Some((from, to)) = cycle_edge_to_remove(cycle) else {
+                    return Ok(false);
+                };
+                Card::from_id(from).unwrap().rm_dependency(to);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// An explicitly added dependency in the cycle, removing it breaks the cycle.
+fn cycle_edge_to_remove(cycle: &[CardId]) -> Option<(CardId, CardId)> {
+    let edges = cycle
+        .iter()
+        .zip(cycle.iter().cycle().skip(1))
+        .map(|(from, to)| (*from, *to));
+
+    for (from, to) in edges {
+        if let Some(card) = Card::from_id(from) {
+            if card.explicit_dependencies().contains(&to) {
+                return Some((from, to));
+            }
+        }
+    }
+
+    None
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+    pub files_checked: usize,
+    /// Cards can't be loaded safely while there are invalid files, so the checks that
+    /// need loaded cards are skipped until those are fixed.
+    pub skipped_card_checks: bool,
+}
+
+impl Report {
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty() && !self.skipped_card_checks
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "checked {} card files", self.files_checked)?;
+        for problem in &self.problems {
+            writeln!(f, "- {}", problem)?;
+        }
+        if self.skipped_card_checks {
+            writeln!(
+                f,
+                "fix the invalid files and run again to check the cards themselves"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn check() -> Report {
+    let mut report = Report::default();
+    let mut ids: BTreeMap<CardId, Vec<PathBuf>> = BTreeMap::new();
+
+    for path in card_files() {
+        report.files_checked += 1;
+        match Card::verify_file(&path) {
+            Ok(id) => ids.entry(id).or_default().push(path),
+            Err(e) => report.problems.push(Problem::InvalidFile {
+                path,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    for (id, paths) in &ids {
+        if paths.len() > 1 {
+            report.problems.push(Problem::DuplicateId {
+                id: *id,
+                paths: paths.clone(),
+            });
+        }
+    }
+
+    // One problem per card, whether its reviews, its review details or both were left behind.
+    let mut orphans: BTreeMap<CardId, PathBuf> = BTreeMap::new();
+    for dir in [get_review_path(), get_review_details_path()] {
+        for path in get_containing_file_paths(&dir, None) {
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            if let Ok(id) = CardId::from_str(&name) {
+                if !ids.contains_key(&id) {
+                    orphans.entry(id).or_insert(path);
+                }
+            }
+        }
+    }
+    for (id, path) in orphans {
+        report.problems.push(Problem::OrphanedReviews { id, path });
+    }
+
+    for (path, error) in crate::attribute::invalid_files() {
+        report
+            .problems
+            .push(Problem::InvalidAttributeFile { path, error });
+    }
+
+    if report.problems.iter().any(|problem| {
+        matches!(
+            problem,
+            Problem::InvalidFile { .. } | Problem::InvalidAttributeFile { .. }
+        )
+    }) {
+        report.skipped_card_checks = true;
+        return report;
+    }
+
+    let cards = Card::load_all_cards();
+    let existing: BTreeSet<CardId> = ids.keys().copied().collect();
+    let classes: BTreeSet<CardId> = cards
+        .iter()
+        .filter(|card| card.is_class())
+        .map(|card| card.id())
+        .collect();
+
+    for card in &cards {
+        for dependency in card.explicit_dependencies() {
+            if !existing.contains(dependency) {
+                report.problems.push(Problem::DanglingDependency {
+                    card: card.id(),
+                    dependency: *dependency,
+                });
+            }
+        }
+
+        if let Some(back) = card.back_side() {
+            for reference in back.dependencies() {
+                if !existing.contains(&reference) {
+                    report.problems.push(Problem::MissingReference {
+                        card: card.id(),
+                        reference,
+                    });
+                }
+            }
+        }
+
+        if let Some(class) = card.class() {
+            if !classes.contains(&class) {
+                report.problems.push(Problem::NotAClass {
+                    card: card.id(),
+                    class,
+                });
+            }
+        }
+
+        if let AnyType::Attribute(attr) = card.card_type() {
+            if !existing.contains(&attr.instance) {
+                report.problems.push(Problem::MissingInstance {
+                    card: card.id(),
+                    instance: attr.instance,
+                });
+            }
+        }
+    }
+
+    for cycle in DependencyGraph::from_cards(&cards).cycles() {
+        report.problems.push(Problem::Cycle(cycle));
+    }
+
+    report
+}
+
+/// Runs the check and repairs what it can, returns the report from before the repairs
+/// along with the problems that were fixed.
+pub fn check_and_repair() -> Result<(Report, Vec<Problem>)> {
+    let report = check();
+    let mut repaired = vec![];
+
+    for problem in &report.problems {
+        if problem.repair()? {
+            repaired.push(problem.clone());
+        }
+    }
+
+    Ok((report, repaired))
+}
+
+/// All card files in the personal cards directory and in the collections.
+pub(crate) fn card_files() -> Vec<PathBuf> {
+    let mut files = vec![];
+
+    for root in [get_cards_path(), get_collections_path()] {
+        for entry in WalkDir::new(root)
+            .into_iter()
+            .filter_entry(|e| !is_hidden(e.path()))
+            .filter_map(|e| e.ok())
+        {
+            let path = entry.path();
+            if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("toml") {
+                files.push(path.to_path_buf());
+            }
+        }
+    }
+
+    files
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
@@ -0,0 +1,272 @@
+use crate::card::AnyType;
+use crate::common::CardId;
+use crate::Card;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// The dependencies between all cards, built from one pass over the cards.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    edges: BTreeMap<CardId, BTreeSet<CardId>>,
+}
+
+impl DependencyGraph {
+    pub fn load() -> Self {
+        Self::from_cards(&Card::load_all_cards())
+    }
+
+    pub fn from_cards(cards: &[Card<AnyType>]) -> Self {
+        Self {
+            edges: cards
+                .iter()
+                .map(|card| (card.id(), card.dependency_ids()))
+                .collect(),
+        }
+    }
+
+    pub fn dependencies(&self, id: CardId) -> impl Iterator<Item = CardId> + '_ {
+        self.edges.get(&id).into_iter().flatten().copied()
+    }
+
+    /// The direct and indirect dependencies of the card, each listed once.
+    pub fn all_dependencies(&self, id: CardId) -> Vec<CardId> {
+        let mut visited = BTreeSet::from([id]);
+        let mut stack = vec![id];
+        let mut dependencies = vec![];
+
+        while let Some(current) = stack.pop() {
+            for dep in self.dependencies(current) {
+                if visited.insert(dep) {
+                    dependencies.push(dep);
+                    stack.push(dep);
+                }
+            }
+        }
+
+        dependencies
+    }
+
+    /// Cards that directly depend on the given card.
+    pub fn dependents(&self, id: CardId) -> Vec<CardId> {
+        self.edges
+            .iter()
+            .filter(|(_, deps)| deps.contains(&id))
+            .map(|(card, _)| *card)
+            .collect()
+    }
+
+    /// Cards that depend on the given card directly or through other cards.
+    pub fn all_dependents(&self, id: CardId) -> Vec<CardId> {
+        let reverse = self.reverse_edges();
+        let mut visited = BTreeSet::from([id]);
+        let mut stack = vec![id];
+        let mut dependents = vec![];
+
+        while let Some(current) = stack.pop() {
+            for dependent in reverse.get(&current).into_iter().flatten() {
+                if visited.insert(*dependent) {
+                    dependents.push(*dependent);
+                    stack.push(*dependent);
+                }
+            }
+        }
+
+        dependents
+    }
+
+    fn reverse_edges(&self) -> BTreeMap<CardId, Vec<CardId>> {
+        let mut reverse: BTreeMap<CardId, Vec<CardId>> = BTreeMap::new();
+        for (id, deps) in &self.edges {
+            for dep in deps {
+                reverse.entry(*dep).or_default().push(*id);
+            }
+        }
+        reverse
+    }
+
+    /// Shortest chain of dependencies from one card to another, including both ends.
+    pub fn path(&self, from: CardId, to: CardId) -> Option<Vec<CardId>> {
+        shortest_path(from, to, |id| self.dependencies(id).collect())
+    }
+
+    /// One cycle from every group of cards that depend on each other.
+    /// Each cycle is listed without repeating the first card at the end.
+    pub fn cycles(&self) -> Vec<Vec<CardId>> {
+        let mut cycles = vec![];
+
+        for component in self.strongly_connected_components() {
+            let first = component[0];
+            let in_component: BTreeSet<CardId> = component.iter().copied().collect();
+
+            let is_cycle = component.len() > 1 || self.dependencies(first).any(|dep| dep == first);
+            if !is_cycle {
+                continue;
+            }
+
+            // Walk from the first card back to itself without leaving the component.
+            let cycle = shortest_path_excluding_start(first, |id| {
+                self.dependencies(id)
+                    .filter(|dep| in_component.contains(dep))
+                    .collect()
+            });
+
+            if let Some(mut cycle) = cycle {
+                cycle.pop();
+                cycles.push(cycle);
+            }
+        }
+
+        cycles
+    }
+
+    /// Kosaraju's algorithm, iterative so that deep graphs don't overflow the stack.
+    fn strongly_connected_components(&self) -> Vec<Vec<CardId>> {
+        let reverse = self.reverse_edges();
+        let mut visited = BTreeSet::new();
+        let mut order = vec![];
+
+        for start in self.edges.keys() {
+            if !visited.insert(*start) {
+                continue;
+            }
+
+            let mut stack = vec![(*start, self.dependencies(*start).collect::<Vec<_>>())];
+            while let Some((node, children)) = stack.last_mut() {
+                match children.pop() {
+                    Some(child) => {
+                        if visited.insert(child) {
+                            let grandchildren = self.dependencies(child).collect();
+                            stack.push((child, grandchildren));
+                        }
+                    }
+                    None => {
+                        order.push(*node);
+                        stack.pop();
+                    }
+                }
+            }
+        }
+
+        let mut assigned = BTreeSet::new();
+        let mut components = vec![];
+
+        for start in order.into_iter().rev() {
+            if !assigned.insert(start) {
+                continue;
+            }
+
+            let mut component = vec![];
+            let mut stack = vec![start];
+            while let Some(node) = stack.pop() {
+                component.push(node);
+                for parent in reverse.get(&node).into_iter().flatten() {
+                    if assigned.insert(*parent) {
+                        stack.push(*parent);
+                    }
+                }
+            }
+            components.push(component);
+        }
+
+        components
+    }
+}
+
+/// Breadth-first search, `neighbours` is only called for cards that are reached.
+pub fn shortest_path(
+    from: CardId,
+    to: CardId,
+    neighbours: impl Fn(CardId) -> Vec<CardId>,
+) -> Option<Vec<CardId>> {
+    if from == to {
+        return Some(vec![from]);
+    }
+
+    let mut came_from: BTreeMap<CardId, CardId> = BTreeMap::new();
+    let mut queue = VecDeque::from([from]);
+
+    while let Some(id) = queue.pop_front() {
+        for next in neighbours(id) {
+            if next == from || came_from.contains_key(&next) {
+                continue;
+            }
+            came_from.insert(next, id);
+
+            if next == to {
+                let mut path = vec![to];
+                let mut current = to;
+                while let Some(prev) = came_from.get(&current) {
+                    path.push(*prev);
+                    current = *prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            queue.push_back(next);
+        }
+    }
+
+    None
+}
+
+/// Like [`shortest_path`] from a card back to itself, the start is included at both ends.
+fn shortest_path_excluding_start(
+    start: CardId,
+    neighbours: impl Fn(CardId) -> Vec<CardId>,
+) -> Option<Vec<CardId>> {
+    let first_steps = neighbours(start);
+    if first_steps.contains(&start) {
+        return Some(vec![start, start]);
+    }
+
+    first_steps
+        .into_iter()
+        .filter_map(|step| shortest_path(step, start, &neighbours))
+        .min_by_key(|path| path.len())
+        .map(|path| std::iter::once(start).chain(path).collect())
+}
+
+/// Finds a chain of dependencies from one card to another by loading cards as they're reached.
+/// Cheaper than building a [`DependencyGraph`] when only a few cards are involved.
+pub fn dependency_path(from: CardId, to: CardId) -> Option<Vec<CardId>> {
+    shortest_path(from, to, |id| {
+        Card::from_id(id)
+            .map(|card| card.dependency_ids().into_iter().collect())
+            .unwrap_or_default()
+    })
+}
+
+/// Whether making `card` depend on `dependency` would create a cycle, and if so the error message.
+pub fn cycle_error(card: CardId, dependency: CardId) -> Option<String> {
+    let path = dependency_path(dependency, card)?;
+    let cycle: Vec<CardId> = std::iter::once(card).chain(path).collect();
+    Some(format!(
+        "dependency would create a cycle: {}",
+        describe_cycle(&cycle)
+    ))
+}
+
+/// The names of the cards in the cycle joined by arrows.
+pub fn describe_cycle(cycle: &[CardId]) -> String {
+    let mut names: Vec<String> = cycle
+        .iter()
+        .map(|id| match Card::from_id(*id) {
+            Some(card) => card.print(),
+            None => id.to_string(),
+        })
+        .collect();
+
+    if cycle.first() != cycle.last() || cycle.len() == 1 {
+        if let Some(first) = names.first().cloned() {
+            names.push(first);
+        }
+    }
+
+    names.join(" -> ")
+}
+
+/// All dependency cycles between cards, class hierarchy cycles show up here too
+/// since a class depends on its parent class.
+pub fn find_cycles() -> Vec<Vec<CardId>> {
+    DependencyGraph::load().cycles()
+}
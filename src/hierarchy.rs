@@ -0,0 +1,193 @@
+use crate::attribute::Attribute;
+use crate::card::AnyType;
+use crate::common::CardId;
+use crate::Card;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The classes, their subclasses and instances, built from one pass over the cards.
+///
+/// Build it once and query it as much as you like, rather than walking up with [`Card::from_id`].
+#[derive(Debug, Clone, Default)]
+pub struct ClassHierarchy {
+    classes: BTreeSet<CardId>,
+    /// Parent class of each class, and the class of each instance.
+    parents: BTreeMap<CardId, CardId>,
+    subclasses: BTreeMap<CardId, BTreeSet<CardId>>,
+    instances: BTreeMap<CardId, BTreeSet<CardId>>,
+}
+
+/// A class with its direct instances and subclasses, recursively.
+#[derive(Debug, Clone)]
+pub struct ClassTree {
+    pub class: CardId,
+    pub instances: Vec<CardId>,
+    pub subclasses: Vec<ClassTree>,
+}
+
+impl ClassTree {
+    /// Number of classes in the tree, including the root.
+    pub fn class_count(&self) -> usize {
+        1 + self
+            .subclasses
+            .iter()
+            .map(|tree| tree.class_count())
+            .sum::<usize>()
+    }
+}
+
+impl ClassHierarchy {
+    pub fn load() -> Self {
+        Self::from_cards(&Card::load_all_cards())
+    }
+
+    pub fn from_cards(cards: &[Card<AnyType>]) -> Self {
+        let mut hierarchy = Self::default();
+
+        for card in cards {
+            match card.card_type() {
+                AnyType::Class(class) => {
+                    hierarchy.classes.insert(card.id());
+                    if let Some(parent) = class.parent_class {
+                        hierarchy.parents.insert(card.id(), parent);
+                        hierarchy
+                            .subclasses
+                            .entry(parent)
+                            .or_default()
+                            .insert(card.id());
+                    }
+                }
+                AnyType::Instance(instance) => {
+                    hierarchy.parents.insert(card.id(), instance.class);
+                    hierarchy
+                        .instances
+                        .entry(instance.class)
+                        .or_default()
+                        .insert(card.id());
+                }
+                _ => {}
+            }
+        }
+
+        hierarchy
+    }
+
+    pub fn is_class(&self, id: CardId) -> bool {
+        self.classes.contains(&id)
+    }
+
+    /// Classes without a parent class.
+    pub fn roots(&self) -> Vec<CardId> {
+        self.classes
+            .iter()
+            .filter(|class| !self.parents.contains_key(class))
+            .copied()
+            .collect()
+    }
+
+    pub fn parent(&self, id: CardId) -> Option<CardId> {
+        self.parents.get(&id).copied()
+    }
+
+    /// The class of the card, its parent class, and so on up to the root.
+    /// Stops if the chain loops back on itself.
+    pub fn ancestors(&self, id: CardId) -> Vec<CardId> {
+        let mut ancestors = vec![];
+        let mut current = self.parent(id);
+
+        while let Some(class) = current {
+            if class == id || ancestors.contains(&class) {
+                break;
+            }
+            ancestors.push(class);
+            current = self.parent(class);
+        }
+
+        ancestors
+    }
+
+    /// Whether the card is the class or belongs to it through any number of parent classes.
+    pub fn is_within(&self, id: CardId, class: CardId) -> bool {
+        id == class || self.ancestors(id).contains(&class)
+    }
+
+    pub fn subclasses(&self, class: CardId) -> Vec<CardId> {
+        self.subclasses
+            .get(&class)
+            .map(|set| set.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// All subclasses, their subclasses, and so on.
+    pub fn descendants(&self, class: CardId) -> Vec<CardId> {
+        let mut visited = BTreeSet::new();
+        let mut stack = self.subclasses(class);
+        let mut descendants = vec![];
+
+        while let Some(sub) = stack.pop() {
+            if sub == class || !visited.insert(sub) {
+                continue;
+            }
+            descendants.push(sub);
+            stack.extend(self.subclasses(sub));
+        }
+
+        descendants
+    }
+
+    /// Instances whose class is exactly this class.
+    pub fn instances(&self, class: CardId) -> Vec<CardId> {
+        self.instances
+            .get(&class)
+            .map(|set| set.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Instances of the class or any of its descendants.
+    pub fn all_instances(&self, class: CardId) -> Vec<CardId> {
+        std::iter::once(class)
+            .chain(self.descendants(class))
+            .flat_map(|class| self.instances(class))
+            .collect()
+    }
+
+    pub fn tree(&self, root: CardId) -> ClassTree {
+        let mut visited = BTreeSet::new();
+        self.subtree(root, &mut visited)
+    }
+
+    fn subtree(&self, class: CardId, visited: &mut BTreeSet<CardId>) -> ClassTree {
+        visited.insert(class);
+
+        let subclasses = self
+            .subclasses(class)
+            .into_iter()
+            .filter(|sub| !visited.contains(sub))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|sub| self.subtree(sub, visited))
+            .collect();
+
+        ClassTree {
+            class,
+            instances: self.instances(class),
+            subclasses,
+        }
+    }
+
+    /// A tree for each root class.
+    pub fn forest(&self) -> Vec<ClassTree> {
+        self.roots()
+            .into_iter()
+            .map(|root| self.tree(root))
+            .collect()
+    }
+
+    /// Attributes defined on the card's class or any of its ancestors.
+    pub fn applicable_attributes(&self, instance: CardId) -> Vec<Attribute> {
+        let classes = self.ancestors(instance);
+        Attribute::load_all()
+            .into_iter()
+            .filter(|attr| classes.contains(&attr.class))
+            .collect()
+    }
+}
@@ -1,27 +1,46 @@
-use attribute::Attribute;
+use answer::AnswerCheck;
 pub use card::Card;
-use card::{AnyType, AttributeCard, CardTrait, InstanceCard, NormalCard, UnfinishedCard};
+use card::{AnyType, Conversion, EventCard, NormalCard, UnfinishedCard};
 use categories::Category;
 use common::CardId;
 use eyre::Result;
+use filter::FilterContext;
 use reviews::Recall;
-use samsvar::Matcher;
 use sanitize_filename::sanitize;
 use std::{
     collections::BTreeSet,
     path::{Path, PathBuf},
 };
+use trash::DeleteMode;
 
+pub mod answer;
 pub mod attribute;
 pub mod card;
 pub mod categories;
+pub mod chronology;
 pub mod collections;
 pub mod common;
 pub mod config;
+pub mod decks;
+pub mod duplicates;
+pub mod filter;
+pub mod fsck;
 pub mod github;
+pub mod graph;
+pub mod hierarchy;
+pub mod media;
+pub mod merge;
+pub mod notes;
 pub mod paths;
+pub mod query;
 pub mod recall_rate;
+pub mod relocate;
+pub mod render;
 pub mod reviews;
+pub mod search;
+pub mod tags;
+pub mod timeline;
+pub mod trash;
 
 pub use timestamped::TimeStamp;
 
@@ -43,9 +62,20 @@ pub fn get_cached_dependents(id: CardId) -> BTreeSet<CardId> {
 }
 
 pub fn cards_filtered(filter: String) -> Vec<CardId> {
-    let mut cards = Card::load_all_cards();
-    cards.retain(|card| card.clone().eval(filter.clone()));
-    cards.iter().map(|card| card.id()).collect()
+    FilterContext::load().filter(&filter)
+}
+
+/// A sorted page of the cards matching the query, see [`query::Query`].
+pub fn query(query: &query::Query) -> Result<query::Page> {
+    query.run()
+}
+
+/// The cards of a saved deck, see [`decks::Deck`].
+pub fn deck_cards(name: &str) -> Result<Vec<CardId>> {
+    match decks::Deck::load(name) {
+        Some(deck) => deck.cards(&FilterContext::load()),
+        None => eyre::bail!("deck '{}' doesn't exist", name),
+    }
 }
 
 pub fn add_card(front: String, back: String, cat: &Category) -> CardId {
@@ -61,39 +91,80 @@ pub fn add_unfinished(front: String, category: &Category) -> CardId {
     Card::<AnyType>::new_unfinished(data, category).id()
 }
 
+pub fn add_event(
+    front: String,
+    start_time: TimeStamp,
+    end_time: Option<TimeStamp>,
+    category: &Category,
+) -> CardId {
+    let data = EventCard {
+        front,
+        start_time,
+        end_time,
+    };
+    Card::<AnyType>::new_event(data, category).id()
+}
+
 pub fn review(card_id: CardId, grade: Recall) {
     let mut card = Card::from_id(card_id).unwrap();
     card.new_review(grade, Default::default());
 }
 
+/// Compares a typed answer with the card's backside and suggests a grade.
+pub fn check_answer(card_id: CardId, answer: &str) -> Option<AnswerCheck> {
+    let card = Card::from_id(card_id)?;
+    let back = card.back_side()?;
+    Some(answer::check_answer(answer, back))
+}
+
 pub fn set_class(card_id: CardId, class: CardId) -> Result<()> {
     let card = Card::from_id(card_id).unwrap();
-
-    let instance = InstanceCard {
-        name: card.card_type().display_front(),
-        class,
-    };
-    card.into_type(instance);
+    card.convert(Conversion::Instance { class })?;
     Ok(())
 }
 
-pub fn set_dependency(card_id: CardId, dependency: CardId) {
-    if card_id == dependency {
-        return;
-    }
-
+pub fn set_dependency(card_id: CardId, dependency: CardId) -> Result<()> {
     let mut card = Card::from_id(card_id).unwrap();
-    card.set_dependency(dependency);
-    card.persist();
+    card.set_dependency(dependency)
+}
+
+pub fn add_tag(card_id: CardId, tag: &str) -> Result<()> {
+    Card::from_id(card_id).unwrap().add_tag(tag, "")
+}
+
+pub fn remove_tag(card_id: CardId, tag: &str) -> bool {
+    Card::from_id(card_id).unwrap().remove_tag(tag)
 }
 
 pub fn card_from_id(card_id: CardId) -> Card<AnyType> {
     Card::from_id(card_id).unwrap()
 }
 
-pub fn delete(card_id: CardId) {
-    let path = Card::from_id(card_id).unwrap().as_path();
-    std::fs::remove_file(path).unwrap();
+/// Moves the card to the trash, see [`trash::restore`] to undo it.
+pub fn delete(card_id: CardId, mode: DeleteMode) -> Result<Vec<CardId>> {
+    trash::delete(card_id, mode)
+}
+
+/// Moves the card file to another category, see [`relocate`] for collections and whole categories.
+pub fn move_card(card_id: CardId, category: &Category) -> Result<()> {
+    relocate::move_card(card_id, category)?;
+    Ok(())
+}
+
+/// Full-text search over fronts, backs and tags, best matches first.
+pub fn search(query: &str) -> Result<Vec<search::SearchHit>> {
+    search::search(query)
+}
+
+/// Groups of cards that look like duplicates of each other.
+pub fn duplicates() -> Vec<duplicates::Cluster> {
+    duplicates::find_duplicates(duplicates::DEFAULT_THRESHOLD)
+}
+
+/// Merges the duplicate into the kept card, see [`merge::merge`].
+pub fn merge(keep: CardId, duplicate: CardId) -> Result<()> {
+    merge::merge(keep, duplicate)?;
+    Ok(())
 }
 
 pub fn as_graph() -> String {
@@ -101,6 +172,10 @@ pub fn as_graph() -> String {
     graphviz::export()
 }
 
+pub fn as_timeline(filter: Option<String>) -> String {
+    timeline::export_html(filter)
+}
+
 pub fn edit(card_id: CardId) {
     Card::from_id(card_id).unwrap().edit_with_vim();
 }
@@ -143,12 +218,10 @@ mod graphviz {
         let cards = Card::load_all_cards();
 
         for card in cards {
-            let label = card
-                .print()
-                .to_string()
-                .replace(")", "")
-                .replace("(", "")
-                .replace("\"", "");
+            let label = render::to_plain_text(&card.print())
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', " ");
 
             let color = match card.recall_rate() {
                 _ if !card.is_finished() => yellow_color(),
@@ -192,52 +265,22 @@ mod graphviz {
     }
 
     // Convert recall rate to a color, from red to green
-    fn rate_to_color(rate: f64) -> String {
+    pub(crate) fn rate_to_color(rate: f64) -> String {
         let red = ((1.0 - rate / 100.0) * 255.0) as u8;
         let green = (rate / 100.0 * 255.0) as u8;
         format!("#{:02X}{:02X}00", red, green) // RGB color in hex
     }
 
-    fn cyan_color() -> String {
+    pub(crate) fn cyan_color() -> String {
         String::from("#00FFFF")
     }
 
-    fn yellow_color() -> String {
+    pub(crate) fn yellow_color() -> String {
         String::from("#FFFF00")
     }
 }
 
-pub fn health_check() {
-    println!("STARTING HEALTH CHECK");
-    verify_attributes();
-    println!("HEALTH CHECK OVER");
-}
-
-fn verify_attributes() {
-    for card in Card::load_all_cards() {
-        if let AnyType::Attribute(AttributeCard {
-            attribute,
-            instance: concept_card,
-            ..
-        }) = card.card_type()
-        {
-            if Attribute::load(*attribute).is_none() {
-                println!("error loading attribute for: {:?}", &card);
-            }
-
-            match Card::from_id(*concept_card) {
-                Some(concept_card) => {
-                    if !card.card_type().is_class() {
-                        println!(
-                            "error, cards concept card is not a concept: {:?} -> {:?}",
-                            &card, concept_card
-                        )
-                    }
-                }
-                None => {
-                    println!("error loading concept card for: {}", &card);
-                }
-            }
-        }
-    }
+/// Checks the integrity of all cards, the report can be displayed as is, see [`fsck`] for repairs.
+pub fn health_check() -> fsck::Report {
+    fsck::check()
 }
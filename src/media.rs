@@ -0,0 +1,209 @@
+use crate::collections::Collection;
+use crate::paths::get_media_path;
+use crate::Card;
+use eyre::{eyre, Result};
+use git2::{ObjectType, Oid};
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Media files of a collection are kept in this hidden directory so it's not mistaken for a category.
+pub const COLLECTION_MEDIA_DIR: &str = ".media";
+
+const REF_PREFIX: &str = "[[media:";
+const REF_SUFFIX: &str = "]]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Image,
+    Audio,
+    Video,
+    Other,
+}
+
+/// A reference to a media file, written in card text as `[[media:<hash>.<ext>]]`.
+///
+/// Files are named after the hash of their content, so importing the same file twice is a no-op.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MediaRef {
+    file_name: String,
+}
+
+impl Display for MediaRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", REF_PREFIX, self.file_name, REF_SUFFIX)
+    }
+}
+
+impl MediaRef {
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn kind(&self) -> MediaKind {
+        let ext = Path::new(&self.file_name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        match ext.as_str() {
+            "png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" | "bmp" => MediaKind::Image,
+            "mp3" | "ogg" | "wav" | "flac" | "m4a" | "opus" => MediaKind::Audio,
+            "mp4" | "webm" | "mkv" | "mov" => MediaKind::Video,
+            _ => MediaKind::Other,
+        }
+    }
+
+    /// Finds the file in the media directory of any collection, or the old personal one.
+    pub fn path(&self) -> Option<PathBuf> {
+        if !is_media_file_name(&self.file_name) {
+            return None;
+        }
+
+        media_dirs()
+            .into_iter()
+            .map(|dir| dir.join(&self.file_name))
+            .find(|path| path.exists())
+    }
+}
+
+/// Whether the name has the `<hash>.<ext>` form that [`import`] gives files.
+///
+/// References come from card text, which can come from someone else's collection,
+/// so anything that could point outside the media directories is refused.
+fn is_media_file_name(file_name: &str) -> bool {
+    let (hash, ext) = match file_name.split_once('.') {
+        Some((hash, ext)) => (hash, Some(ext)),
+        None => (file_name, None),
+    };
+
+    !hash.is_empty()
+        && hash.chars().all(|c| c.is_ascii_hexdigit())
+        && ext.map_or(true, |ext| {
+            !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Copies the file into the media directory of the collection, or of the default collection if `None`,
+/// so it's synced along with the cards that refer to it.
+pub fn import(path: &Path, collection: Option<&Collection>) -> Result<MediaRef> {
+    let data = fs::read(path)?;
+    let hash = Oid::hash_object(ObjectType::Blob, &data)?;
+
+    let file_name = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => format!("{}.{}", hash, ext.to_lowercase()),
+        None => hash.to_string(),
+    };
+
+    let dir = match collection {
+        Some(col) => collection_media_path(col),
+        None => collection_media_path(&Collection::default()),
+    };
+
+    let target = dir.join(&file_name);
+    if !target.exists() {
+        fs::write(&target, data)?;
+    }
+
+    Ok(MediaRef { file_name })
+}
+
+/// Looks up a media file by its file name, as written in a reference.
+pub fn resolve(file_name: &str) -> Option<PathBuf> {
+    MediaRef {
+        file_name: file_name.to_string(),
+    }
+    .path()
+}
+
+/// All media references in the text, in the order they appear.
+pub fn references_in(text: &str) -> Vec<MediaRef> {
+    let mut refs = vec![];
+    let mut rest = text;
+
+    while let Some(start) = rest.find(REF_PREFIX) {
+        rest = &rest[start + REF_PREFIX.len()..];
+        let Some(end) = rest.find(REF_SUFFIX) else {
+            break;
+        };
+
+        let file_name = rest[..end].trim();
+        if is_media_file_name(file_name) {
+            refs.push(MediaRef {
+                file_name: file_name.to_string(),
+            });
+        }
+        rest = &rest[end + REF_SUFFIX.len()..];
+    }
+
+    refs
+}
+
+/// Removes the media references from the text, for clients that can't show media.
+pub fn strip_references(text: &str) -> String {
+    let mut text = text.to_string();
+    for reff in references_in(&text) {
+        text = text.replace(&reff.to_string(), "");
+    }
+    text.trim().to_string()
+}
+
+/// Media referenced by any card, including cards in the trash as they can still be restored.
+pub fn referenced_media() -> BTreeSet<MediaRef> {
+    let trashed = crate::trash::card_files()
+        .into_iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|s| references_in(&s));
+
+    Card::load_all_cards()
+        .into_iter()
+        .flat_map(|card| card.media())
+        .chain(trashed)
+        .collect()
+}
+
+/// Media files that exist in a media directory but which no card refers to.
+pub fn unreferenced_media() -> Vec<PathBuf> {
+    let referenced: BTreeSet<String> = referenced_media()
+        .into_iter()
+        .map(|reff| reff.file_name)
+        .collect();
+
+    media_dirs()
+        .into_iter()
+        .flat_map(|dir| crate::get_containing_file_paths(&dir, None))
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            !referenced.contains(name.as_ref())
+        })
+        .collect()
+}
+
+/// Deletes all unreferenced media files, returns the paths of the removed files.
+pub fn garbage_collect() -> Result<Vec<PathBuf>> {
+    let unreferenced = unreferenced_media();
+    for path in &unreferenced {
+        fs::remove_file(path).map_err(|e| eyre!("failed to remove {}: {}", path.display(), e))?;
+    }
+    Ok(unreferenced)
+}
+
+pub fn collection_media_path(collection: &Collection) -> PathBuf {
+    let path = collection.path().join(COLLECTION_MEDIA_DIR);
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+/// Media used to be imported into the share directory by default, which isn't synced.
+fn media_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![get_media_path()];
+    for col in Collection::load_all() {
+        let path = col.path().join(COLLECTION_MEDIA_DIR);
+        if path.exists() {
+            dirs.push(path);
+        }
+    }
+    dirs
+}
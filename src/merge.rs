@@ -0,0 +1,104 @@
+use crate::attribute::Attribute;
+use crate::card::AnyType;
+use crate::common::CardId;
+use crate::graph::{cycle_error, DependencyGraph};
+use crate::trash::move_to_trash;
+use crate::Card;
+use eyre::{bail, Result};
+
+/// Merges the `duplicate` card into the `keep` card.
+///
+/// The kept card gets the union of both cards' dependencies and tags and both review histories.
+/// Every reference to the duplicate, from other cards and from attributes, is pointed at the kept
+/// card, and the duplicate is moved to the trash.
+pub fn merge(keep: CardId, duplicate: CardId) -> Result<Card<AnyType>> {
+    if keep == duplicate {
+        bail!("can't merge a card with itself");
+    }
+
+    let Some(mut kept) = Card::from_id(keep) else {
+        bail!("no card with id {}", keep);
+    };
+    let Some(removed) = Card::from_id(duplicate) else {
+        bail!("no card with id {}", duplicate);
+    };
+
+    let graph = DependencyGraph::load();
+    if graph.path(keep, duplicate).is_some() || graph.path(duplicate, keep).is_some() {
+        bail!(
+            "can't merge '{}' and '{}' as one depends on the other",
+            kept.print(),
+            removed.print()
+        );
+    }
+
+    let attributes = Attribute::load_all();
+    for attr in &attributes {
+        if attr.class == duplicate && !kept.is_class() {
+            bail!(
+                "'{}' has attributes, it can only be merged into a class",
+                removed.print()
+            );
+        }
+        if attr.back_type == Some(duplicate) && !kept.is_class() {
+            bail!(
+                "'{}' is the answer type of '{}', it can only be merged into a class",
+                removed.print(),
+                attr.pattern
+            );
+        }
+    }
+
+    // Everything is checked before anything is changed so a failed merge leaves the cards as they were.
+    let mut dependents = vec![];
+    for dependent in graph.dependents(duplicate) {
+        if dependent == keep {
+            continue;
+        }
+        if let Some(err) = cycle_error(dependent, keep) {
+            bail!(err);
+        }
+        let Some(card) = Card::from_id(dependent) else {
+            continue;
+        };
+        if let Err(err) = card.verify_replace_reference(duplicate, keep) {
+            bail!(
+                "can't point '{}' at '{}': {}",
+                card.print(),
+                kept.print(),
+                err
+            );
+        }
+        dependents.push(card);
+    }
+
+    kept.absorb(&removed);
+
+    for card in dependents {
+        card.replace_reference(duplicate, keep);
+    }
+
+    for mut attr in attributes {
+        let mut changed = false;
+
+        if attr.class == duplicate {
+            attr.class = keep;
+            changed = true;
+        }
+        if attr.back_type == Some(duplicate) {
+            attr.back_type = Some(keep);
+            changed = true;
+        }
+        if attr.dependencies.remove(&duplicate) {
+            attr.dependencies.insert(keep);
+            changed = true;
+        }
+
+        if changed {
+            attr.save()?;
+        }
+    }
+
+    move_to_trash(removed)?;
+    Ok(Card::from_id(keep).unwrap())
+}
@@ -0,0 +1,296 @@
+use crate::card::{AnyType, NormalCard};
+use crate::categories::Category;
+use crate::collections::Collection;
+use crate::common::CardId;
+use crate::paths::get_note_types_path;
+use crate::{get_containing_file_paths, my_sanitize_filename, Card};
+use eyre::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Notes are saved in the category directory with this extension, so they're not loaded as cards.
+pub const NOTE_EXTENSION: &str = "note";
+
+/// Note types are saved in this hidden directory of a collection so they sync along with the cards.
+pub const COLLECTION_NOTE_TYPES_DIR: &str = ".note_types";
+
+/// Extension of note type files, for the same reason as [`NOTE_EXTENSION`].
+pub const NOTE_TYPE_EXTENSION: &str = "notetype";
+
+/// Tag of cards whose template no longer produces a card, as a field it refers to was emptied.
+/// The value is the name of the template.
+pub const STALE_TAG: &str = "note::stale";
+
+/// Front and back of a card generated from a note, fields are referenced like `{{infinitive}}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardTemplate {
+    pub name: String,
+    pub front: String,
+    pub back: String,
+}
+
+/// Describes a kind of structured material, for example a verb with a field per conjugation,
+/// and the cards that should be made from it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteType {
+    pub name: String,
+    pub fields: Vec<String>,
+    pub templates: Vec<CardTemplate>,
+}
+
+impl NoteType {
+    pub fn new(name: impl Into<String>, fields: Vec<String>) -> Self {
+        Self {
+            name: name.into(),
+            fields,
+            templates: vec![],
+        }
+    }
+
+    /// Adds a template, failing if it refers to a field the note type doesn't have.
+    pub fn add_template(
+        &mut self,
+        name: impl Into<String>,
+        front: impl Into<String>,
+        back: impl Into<String>,
+    ) -> Result<()> {
+        let template = CardTemplate {
+            name: name.into(),
+            front: front.into(),
+            back: back.into(),
+        };
+
+        if self.templates.iter().any(|t| t.name == template.name) {
+            bail!("note type already has a template named '{}'", template.name);
+        }
+
+        for field in placeholders(&template.front)
+            .into_iter()
+            .chain(placeholders(&template.back))
+        {
+            if !self.fields.contains(&field) {
+                bail!(
+                    "template '{}' refers to unknown field '{}'",
+                    template.name,
+                    field
+                );
+            }
+        }
+
+        self.templates.push(template);
+        Ok(())
+    }
+
+    /// The file of the note type in whichever collection it's stored in.
+    fn find(name: &str) -> Option<PathBuf> {
+        let file_name = my_sanitize_filename(name);
+        collection_note_type_files().into_iter().find(|path| {
+            path.file_stem()
+                .is_some_and(|stem| stem == file_name.as_str())
+        })
+    }
+
+    /// Note types used to be saved in the share directory, which isn't synced.
+    fn legacy_path(name: &str) -> PathBuf {
+        let mut path = get_note_types_path().join(my_sanitize_filename(name));
+        path.set_extension("toml");
+        path
+    }
+
+    /// Saves the note type in its collection, note types from the share directory are moved
+    /// to the default collection.
+    pub fn save(&self) -> Result<()> {
+        let path = Self::find(&self.name).unwrap_or_else(|| {
+            let mut path = collection_note_types_path(&Collection::default())
+                .join(my_sanitize_filename(&self.name));
+            path.set_extension(NOTE_TYPE_EXTENSION);
+            path
+        });
+        let s = toml::to_string_pretty(self)?;
+        fs::write(path, s)?;
+
+        let legacy_path = Self::legacy_path(&self.name);
+        if legacy_path.exists() {
+            fs::remove_file(legacy_path)?;
+        }
+        Ok(())
+    }
+
+    pub fn load(name: &str) -> Option<Self> {
+        let path = Self::find(name).unwrap_or_else(|| Self::legacy_path(name));
+        let s = fs::read_to_string(path).ok()?;
+        toml::from_str(&s).ok()
+    }
+
+    /// Note types in every collection, files that can't be read are skipped.
+    pub fn load_all() -> Vec<Self> {
+        collection_note_type_files()
+            .into_iter()
+            .chain(get_containing_file_paths(
+                &get_note_types_path(),
+                Some("toml"),
+            ))
+            .filter_map(|path| {
+                let s = fs::read_to_string(path).ok()?;
+                toml::from_str(&s).ok()
+            })
+            .collect()
+    }
+}
+
+pub fn collection_note_types_path(collection: &Collection) -> PathBuf {
+    let path = collection.path().join(COLLECTION_NOTE_TYPES_DIR);
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn collection_note_type_files() -> Vec<PathBuf> {
+    let mut files = vec![];
+    for col in Collection::load_all() {
+        let dir = col.path().join(COLLECTION_NOTE_TYPES_DIR);
+        if dir.exists() {
+            files.extend(get_containing_file_paths(&dir, Some(NOTE_TYPE_EXTENSION)));
+        }
+    }
+    files
+}
+
+/// The field values for one piece of material, which the cards of its note type are generated from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Note {
+    pub id: Uuid,
+    pub note_type: String,
+    pub fields: BTreeMap<String, String>,
+    /// Cards previously generated from this note, by template name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cards: BTreeMap<String, CardId>,
+}
+
+impl Note {
+    pub fn new(note_type: &NoteType, fields: BTreeMap<String, String>) -> Result<Self> {
+        for field in fields.keys() {
+            if !note_type.fields.contains(field) {
+                bail!("note type '{}' has no field '{}'", note_type.name, field);
+            }
+        }
+
+        Ok(Self {
+            id: Uuid::new_v4(),
+            note_type: note_type.name.clone(),
+            fields,
+            cards: Default::default(),
+        })
+    }
+
+    /// Creates or updates the cards of every template, and saves the note in the category.
+    ///
+    /// Templates referring to an empty field are skipped, if they generated a card before it's
+    /// tagged with [`STALE_TAG`]. Cards that were generated before are updated in place, so they
+    /// keep their reviews.
+    pub fn generate(&mut self, category: &Category) -> Result<Vec<CardId>> {
+        let Some(note_type) = NoteType::load(&self.note_type) else {
+            bail!("no note type named '{}'", self.note_type);
+        };
+
+        let mut generated = vec![];
+
+        for template in &note_type.templates {
+            let previous = self
+                .cards
+                .get(&template.name)
+                .and_then(|id| Card::from_id(*id));
+
+            let (Some(front), Some(back)) =
+                (self.fill(&template.front)?, self.fill(&template.back)?)
+            else {
+                if let Some(mut card) = previous {
+                    card.add_tag(STALE_TAG, &template.name)?;
+                }
+                continue;
+            };
+
+            let data = NormalCard {
+                front,
+                back: back.into(),
+            };
+
+            let card = match previous {
+                Some(mut card) => {
+                    card.remove_tag(STALE_TAG);
+                    card.into_type(data)
+                }
+                None => Card::<AnyType>::new_normal(data, category),
+            };
+
+            self.cards.insert(template.name.clone(), card.id());
+            generated.push(card.id());
+        }
+
+        self.save_at(&category.as_path())?;
+        Ok(generated)
+    }
+
+    /// Replaces the placeholders with field values, `None` if a referenced field is empty.
+    fn fill(&self, template: &str) -> Result<Option<String>> {
+        let mut text = template.to_string();
+
+        for field in placeholders(template) {
+            let value = self
+                .fields
+                .get(&field)
+                .map(|s| s.trim())
+                .unwrap_or_default();
+            if value.is_empty() {
+                return Ok(None);
+            }
+            text = text.replace(&format!("{{{{{}}}}}", field), value);
+        }
+
+        if text.trim().is_empty() {
+            bail!("template '{}' produced an empty card", template);
+        }
+
+        Ok(Some(text))
+    }
+
+    pub fn save_at(&self, dir: &Path) -> Result<()> {
+        let mut path = dir.join(self.id.to_string());
+        path.set_extension(NOTE_EXTENSION);
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Notes saved in the category, files that can't be read are skipped.
+    pub fn load_from_category(category: &Category) -> Vec<Self> {
+        get_containing_file_paths(&category.as_path(), Some(NOTE_EXTENSION))
+            .into_iter()
+            .filter_map(|path| {
+                let s = fs::read_to_string(path).ok()?;
+                toml::from_str(&s).ok()
+            })
+            .collect()
+    }
+}
+
+/// Names of the fields referenced in the template, in order of appearance.
+pub fn placeholders(template: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("}}") else {
+            break;
+        };
+        let field = rest[..end].to_string();
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+        rest = &rest[end + 2..];
+    }
+
+    fields
+}
@@ -21,6 +21,18 @@ pub fn get_review_path() -> PathBuf {
     path
 }
 
+pub fn get_review_details_path() -> PathBuf {
+    let path = get_share_path().join("review_details");
+    create_dir_all(&path).unwrap();
+    path
+}
+
+pub fn get_revisions_path() -> PathBuf {
+    let path = get_share_path().join("revisions");
+    create_dir_all(&path).unwrap();
+    path
+}
+
 pub fn get_collections_path() -> PathBuf {
     let path = get_share_path().join("collections");
     create_dir_all(&path).unwrap();
@@ -45,6 +57,38 @@ pub fn get_attributes_path() -> PathBuf {
     path
 }
 
+/// Attributes of collections, one directory per collection. They're kept out of the collections
+/// directory as everything in there is loaded as a card.
+pub fn get_collection_attributes_path() -> PathBuf {
+    let path = get_share_path().join("collection_attributes");
+    create_dir_all(&path).unwrap();
+    path
+}
+
+pub fn get_note_types_path() -> PathBuf {
+    let path = get_share_path().join("note_types");
+    create_dir_all(&path).unwrap();
+    path
+}
+
+pub fn get_trash_path() -> PathBuf {
+    let path = get_share_path().join("trash");
+    create_dir_all(&path).unwrap();
+    path
+}
+
+pub fn get_media_path() -> PathBuf {
+    let path = get_share_path().join("media");
+    create_dir_all(&path).unwrap();
+    path
+}
+
+pub fn get_decks_path() -> PathBuf {
+    let path = get_share_path().join("decks");
+    create_dir_all(&path).unwrap();
+    path
+}
+
 #[cfg(not(test))]
 pub fn get_share_path() -> PathBuf {
     let home = dirs::home_dir().unwrap();
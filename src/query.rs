@@ -0,0 +1,98 @@
+use crate::common::CardId;
+use crate::filter::{FilterContext, SortKey};
+use crate::search::SearchIndex;
+use eyre::Result;
+
+/// Keys the search index can sort by without loading the cards. The index only has the fronts
+/// as plain text, sorting by them would order pages differently than with a filter.
+const INDEXED_KEYS: [&str; 1] = ["id"];
+
+/// A filtered, sorted page of cards.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub filter: Option<String>,
+    /// Any filter key, the first key decides the order and the next ones break ties.
+    pub sort: Vec<SortKey>,
+    pub offset: usize,
+    /// All the remaining cards if `None`.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Page {
+    pub ids: Vec<CardId>,
+    /// How many cards matched the query, on all pages.
+    pub total: usize,
+}
+
+impl Query {
+    pub fn new(filter: impl Into<String>) -> Self {
+        Self {
+            filter: Some(filter.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn sort_by(mut self, key: SortKey) -> Self {
+        self.sort.push(key);
+        self
+    }
+
+    pub fn page(mut self, offset: usize, limit: usize) -> Self {
+        self.offset = offset;
+        self.limit = Some(limit);
+        self
+    }
+
+    fn paginate(&self, ids: Vec<CardId>) -> Page {
+        let total = ids.len();
+        let ids = ids
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+        Page { ids, total }
+    }
+
+    /// Runs the query, using the search index instead of loading the cards when there's
+    /// no filter and the sort keys are ones the index has.
+    pub fn run(&self) -> Result<Page> {
+        let filter = self.filter.as_deref().map(str::trim).unwrap_or_default();
+        let indexed = self
+            .sort
+            .iter()
+            .all(|key| INDEXED_KEYS.contains(&key.key.as_str()));
+
+        if filter.is_empty() && indexed {
+            let mut index = SearchIndex::load();
+            if index.refresh() > 0 {
+                index.save()?;
+            }
+            return Ok(self.run_indexed(&index));
+        }
+
+        Ok(self.run_with(&FilterContext::load()))
+    }
+
+    /// Runs the query against cards that are already loaded.
+    pub fn run_with(&self, ctx: &FilterContext) -> Page {
+        let mut ids = match self.filter.as_deref().map(str::trim) {
+            Some(filter) if !filter.is_empty() => ctx.filter(filter),
+            _ => ctx.cards().map(|card| card.id()).collect(),
+        };
+        ids.sort();
+        ctx.sort(&mut ids, &self.sort);
+        self.paginate(ids)
+    }
+
+    fn run_indexed(&self, index: &SearchIndex) -> Page {
+        let mut ids: Vec<CardId> = index.ids().collect();
+
+        // The ids are unique, so only the first key matters.
+        if self.sort.first().is_some_and(|key| key.descending) {
+            ids.reverse();
+        }
+
+        self.paginate(ids)
+    }
+}
@@ -60,15 +60,32 @@ fn stability(reviews: &Reviews) -> Option<Duration> {
     Some(stability)
 }
 
-pub fn recall_rate(reviews: &Reviews, current_unix: Duration) -> Option<RecallRate> {
-    let days_passed = reviews.time_since_last_review(current_unix)?;
+/// Recall rate at which a card is due for review, unless its category says otherwise.
+pub const DEFAULT_TARGET_RECALL: f32 = 0.9;
+
+fn randomized_stability(reviews: &Reviews) -> Option<Duration> {
     let stability = stability(reviews)?;
     let randomized_stability =
         randomize_factor(stability.as_secs_f32(), reviews.0.last().unwrap().timestamp);
-    let stability = Duration::from_secs_f32(randomized_stability);
+    Some(Duration::from_secs_f32(randomized_stability))
+}
+
+pub fn recall_rate(reviews: &Reviews, current_unix: Duration) -> Option<RecallRate> {
+    let days_passed = reviews.time_since_last_review(current_unix)?;
+    let stability = randomized_stability(reviews)?;
     Some(calculate_recall_rate(&days_passed, &stability))
 }
 
+/// Days until the recall rate drops to the target, negative if it's already below it.
+pub fn due_in_days(reviews: &Reviews, current_unix: Duration, target: RecallRate) -> Option<f32> {
+    let time_passed = reviews.time_since_last_review(current_unix)?;
+    let stability = randomized_stability(reviews)?;
+
+    // Inverse of `calculate_recall_rate`.
+    let due_after = stability.as_secs_f32() * target.ln() / 0.9_f32.ln();
+    Some((due_after - time_passed.as_secs_f32()) / 86400.)
+}
+
 fn calculate_recall_rate(days_passed: &Duration, stability: &Duration) -> RecallRate {
     let base: f32 = 0.9;
     let ratio = days_passed.as_secs_f32() / stability.as_secs_f32();
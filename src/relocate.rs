@@ -0,0 +1,115 @@
+use crate::card::AnyType;
+use crate::categories::{validate_name, Category};
+use crate::collections::{commit_paths, Collection};
+use crate::common::CardId;
+use crate::Card;
+use eyre::{bail, Result};
+use git2::Repository;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Moves the card file to another category, its id and reviews stay the same.
+pub fn move_card(id: CardId, to: &Category) -> Result<Card<AnyType>> {
+    let Some(card) = Card::from_id(id) else {
+        bail!("no card with id {}", id);
+    };
+
+    let from = card.category().clone();
+    if &from == to {
+        return Ok(card);
+    }
+
+    let old_path = card.as_path();
+    let dir = to.as_path();
+    fs::create_dir_all(&dir)?;
+
+    let new_path = dir.join(old_path.file_name().unwrap());
+    if new_path.exists() {
+        bail!("there's already a card file at {}", new_path.display());
+    }
+    fs::rename(&old_path, &new_path)?;
+
+    let message = format!("move '{}' to {}", card.print(), to.print_full());
+    commit_changes(
+        &[(&from, old_path.as_path()), (to, new_path.as_path())],
+        &message,
+    )?;
+
+    Ok(Card::from_id(id).unwrap())
+}
+
+/// Moves the card to the same category in another collection, or to the personal cards if `None`.
+pub fn move_to_collection(id: CardId, collection: Option<&Collection>) -> Result<Card<AnyType>> {
+    let Some(card) = Card::from_id(id) else {
+        bail!("no card with id {}", id);
+    };
+    let to = card.category().in_collection(collection);
+    move_card(id, &to)
+}
+
+/// Moves the category with its cards and subcategories, `to` must not exist yet.
+pub fn move_category(from: &Category, to: &Category) -> Result<()> {
+    if from.is_root() {
+        bail!("can't move the top level category");
+    }
+
+    let (old_path, new_path) = (from.as_path(), to.as_path());
+    if !old_path.exists() {
+        bail!("category {} doesn't exist", from.print_full());
+    }
+    if to.is_within(from) {
+        bail!("can't move category {} into itself", from.print_full());
+    }
+    if new_path.exists() {
+        bail!("category {} already exists", to.print_full());
+    }
+
+    if let Some(parent) = new_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(&old_path, &new_path)?;
+
+    let message = format!("move category {} to {}", from.print_full(), to.print_full());
+    commit_changes(
+        &[(from, old_path.as_path()), (to, new_path.as_path())],
+        &message,
+    )
+}
+
+/// Renames the last part of the category path, returns the renamed category.
+pub fn rename_category(category: &Category, name: &str) -> Result<Category> {
+    validate_name(name)?;
+    let Some(parent) = category.parent() else {
+        bail!("can't rename the top level category");
+    };
+
+    let renamed = parent.join(name);
+    move_category(category, &renamed)?;
+    Ok(renamed)
+}
+
+/// Commits the paths that were moved from and to in the git repo of their category, once per repo.
+/// Other changes in the repos are left alone. Directories that aren't repos are skipped.
+fn commit_changes(changes: &[(&Category, &Path)], message: &str) -> Result<()> {
+    let mut repos: Vec<(PathBuf, Vec<PathBuf>)> = vec![];
+    for (category, path) in changes {
+        let root = category.collection_path();
+        let Ok(relative) = path.strip_prefix(&root) else {
+            continue;
+        };
+
+        match repos.iter_mut().find(|(r, _)| r == &root) {
+            Some((_, paths)) => paths.push(relative.to_path_buf()),
+            None => repos.push((root, vec![relative.to_path_buf()])),
+        }
+    }
+
+    for (root, paths) in repos {
+        if root.join(".git").exists() {
+            let repo = Repository::open(&root)?;
+            commit_paths(&repo, &paths, message)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,522 @@
+use crate::media::{self, MediaKind, MediaRef};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Strong(Vec<Inline>),
+    Emphasis(Vec<Inline>),
+    Code(String),
+    Math(String),
+    Link { text: Vec<Inline>, url: String },
+    Media(MediaRef),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading {
+        level: usize,
+        content: Vec<Inline>,
+    },
+    Paragraph(Vec<Inline>),
+    Quote(Vec<Inline>),
+    List {
+        ordered: bool,
+        items: Vec<Vec<Inline>>,
+    },
+    Code {
+        lang: Option<String>,
+        code: String,
+    },
+    Math(String),
+}
+
+/// Card text parsed as markdown with TeX math, so every client renders it the same way.
+///
+/// Supports headings, paragraphs, quotes, lists, fenced code blocks, `$$` display math,
+/// and inline `**strong**`, `*emphasis*`, `` `code` ``, `$math$`, `[links](url)` and media references.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+}
+
+impl Document {
+    pub fn parse(text: &str) -> Self {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut blocks = vec![];
+        let mut paragraph: Vec<&str> = vec![];
+        let mut i = 0;
+
+        fn flush(paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>) {
+            if !paragraph.is_empty() {
+                blocks.push(Block::Paragraph(parse_inline(&paragraph.join(" "))));
+                paragraph.clear();
+            }
+        }
+
+        while i < lines.len() {
+            let line = lines[i];
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() {
+                flush(&mut paragraph, &mut blocks);
+                i += 1;
+            } else if let Some(lang) = trimmed.strip_prefix("```") {
+                flush(&mut paragraph, &mut blocks);
+                let lang = Some(lang.trim().to_string()).filter(|lang| !lang.is_empty());
+                let mut code = vec![];
+                i += 1;
+                while i < lines.len() && !lines[i].trim().starts_with("```") {
+                    code.push(lines[i]);
+                    i += 1;
+                }
+                blocks.push(Block::Code {
+                    lang,
+                    code: code.join("\n"),
+                });
+                i += 1;
+            } else if let Some(rest) = trimmed.strip_prefix("$$") {
+                flush(&mut paragraph, &mut blocks);
+                let mut math = vec![];
+                let mut rest = rest;
+                loop {
+                    if let Some(end) = rest.find("$$") {
+                        math.push(&rest[..end]);
+                        break;
+                    }
+                    math.push(rest);
+                    i += 1;
+                    if i == lines.len() {
+                        break;
+                    }
+                    rest = lines[i];
+                }
+                blocks.push(Block::Math(math.join("\n").trim().to_string()));
+                i += 1;
+            } else if let Some((level, content)) = heading(trimmed) {
+                flush(&mut paragraph, &mut blocks);
+                blocks.push(Block::Heading {
+                    level,
+                    content: parse_inline(content),
+                });
+                i += 1;
+            } else if trimmed.starts_with('>') {
+                flush(&mut paragraph, &mut blocks);
+                let mut quote = vec![];
+                while i < lines.len() && lines[i].trim().starts_with('>') {
+                    quote.push(lines[i].trim()[1..].trim());
+                    i += 1;
+                }
+                blocks.push(Block::Quote(parse_inline(&quote.join(" "))));
+            } else if let Some((ordered, _)) = list_item(trimmed) {
+                flush(&mut paragraph, &mut blocks);
+                let mut items = vec![];
+                while i < lines.len() {
+                    match list_item(lines[i].trim()) {
+                        Some((item_ordered, item)) if item_ordered == ordered => {
+                            items.push(parse_inline(item));
+                            i += 1;
+                        }
+                        _ => break,
+                    }
+                }
+                blocks.push(Block::List { ordered, items });
+            } else {
+                paragraph.push(trimmed);
+                i += 1;
+            }
+        }
+
+        flush(&mut paragraph, &mut blocks);
+        Self { blocks }
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        for block in &self.blocks {
+            match block {
+                Block::Heading { level, content } => html.push_str(&format!(
+                    "<h{0}>{1}</h{0}>\n",
+                    level,
+                    inlines_to_html(content)
+                )),
+                Block::Paragraph(content) => {
+                    html.push_str(&format!("<p>{}</p>\n", inlines_to_html(content)))
+                }
+                Block::Quote(content) => html.push_str(&format!(
+                    "<blockquote>{}</blockquote>\n",
+                    inlines_to_html(content)
+                )),
+                Block::List { ordered, items } => {
+                    let tag = if *ordered { "ol" } else { "ul" };
+                    html.push_str(&format!("<{}>\n", tag));
+                    for item in items {
+                        html.push_str(&format!("<li>{}</li>\n", inlines_to_html(item)));
+                    }
+                    html.push_str(&format!("</{}>\n", tag));
+                }
+                Block::Code { lang, code } => match lang {
+                    Some(lang) => html.push_str(&format!(
+                        "<pre><code class=\"language-{}\">{}</code></pre>\n",
+                        escape_html(lang),
+                        escape_html(code)
+                    )),
+                    None => {
+                        html.push_str(&format!("<pre><code>{}</code></pre>\n", escape_html(code)))
+                    }
+                },
+                Block::Math(math) => html.push_str(&format!(
+                    "<div class=\"math display\">\\[{}\\]</div>\n",
+                    escape_html(math)
+                )),
+            }
+        }
+        html
+    }
+
+    /// The text without any markup, math is kept as its TeX source and media is left out.
+    pub fn to_plain_text(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|block| match block {
+                Block::Heading { content, .. }
+                | Block::Paragraph(content)
+                | Block::Quote(content) => inlines_to_plain(content),
+                Block::List { items, .. } => items
+                    .iter()
+                    .map(|item| inlines_to_plain(item))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                Block::Code { code, .. } => code.clone(),
+                Block::Math(math) => math.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+pub fn to_html(text: &str) -> String {
+    Document::parse(text).to_html()
+}
+
+pub fn to_plain_text(text: &str) -> String {
+    Document::parse(text).to_plain_text()
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    line[level..]
+        .strip_prefix(' ')
+        .map(|content| (level, content.trim()))
+}
+
+/// Returns whether the item is ordered, and its content.
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(item) = line.strip_prefix(bullet) {
+            return Some((false, item));
+        }
+    }
+
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        if let Some(item) = line[digits..].strip_prefix(". ") {
+            return Some((true, item));
+        }
+    }
+
+    None
+}
+
+pub fn parse_inline(text: &str) -> Vec<Inline> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut inlines = vec![];
+    let mut buf = String::new();
+    let mut i = 0;
+
+    fn flush(buf: &mut String, inlines: &mut Vec<Inline>) {
+        if !buf.is_empty() {
+            inlines.push(Inline::Text(std::mem::take(buf)));
+        }
+    }
+
+    // Index of the next occurrence of the delimiter at or after `from`.
+    fn find(chars: &[char], from: usize, delim: &str) -> Option<usize> {
+        let delim: Vec<char> = delim.chars().collect();
+        (from..chars.len()).find(|&idx| chars[idx..].starts_with(&delim))
+    }
+
+    // Whether the delimiter starts at `at`, without copying the rest of the text.
+    fn starts_at(chars: &[char], at: usize, delim: &str) -> bool {
+        let mut rest = chars[at..].iter();
+        delim.chars().all(|d| rest.next() == Some(&d))
+    }
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '\\' && i + 1 < chars.len() {
+            buf.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+
+        if starts_at(&chars, i, "[[media:") {
+            if let Some(end) = find(&chars, i, "]]") {
+                let reference: String = chars[i..end + 2].iter().collect();
+                if let Some(media) = media::references_in(&reference).pop() {
+                    flush(&mut buf, &mut inlines);
+                    inlines.push(Inline::Media(media));
+                    i = end + 2;
+                    continue;
+                }
+            }
+        }
+
+        if c == '`' {
+            if let Some(end) = find(&chars, i + 1, "`") {
+                flush(&mut buf, &mut inlines);
+                inlines.push(Inline::Code(chars[i + 1..end].iter().collect()));
+                i = end + 1;
+                continue;
+            }
+        }
+
+        // `$` only opens math when followed by non-whitespace, so prices like "$5 and $10" stay text.
+        if c == '$'
+            && chars
+                .get(i + 1)
+                .is_some_and(|next| !next.is_whitespace() && *next != '$')
+        {
+            if let Some(end) =
+                find(&chars, i + 1, "$").filter(|end| !chars[end - 1].is_whitespace())
+            {
+                flush(&mut buf, &mut inlines);
+                inlines.push(Inline::Math(chars[i + 1..end].iter().collect()));
+                i = end + 1;
+                continue;
+            }
+        }
+
+        if starts_at(&chars, i, "**") || starts_at(&chars, i, "__") {
+            let delim: String = chars[i..i + 2].iter().collect();
+            if let Some(end) = find(&chars, i + 2, &delim).filter(|end| *end > i + 2) {
+                flush(&mut buf, &mut inlines);
+                let inner: String = chars[i + 2..end].iter().collect();
+                inlines.push(Inline::Strong(parse_inline(&inner)));
+                i = end + 2;
+                continue;
+            }
+        }
+
+        // Underscores inside words like snake_case don't start emphasis.
+        let intraword = c == '_' && i > 0 && chars[i - 1].is_alphanumeric();
+        if (c == '*' || c == '_') && !intraword {
+            let delim = c.to_string();
+            if let Some(end) = find(&chars, i + 1, &delim).filter(|end| *end > i + 1) {
+                flush(&mut buf, &mut inlines);
+                let inner: String = chars[i + 1..end].iter().collect();
+                inlines.push(Inline::Emphasis(parse_inline(&inner)));
+                i = end + 1;
+                continue;
+            }
+        }
+
+        if c == '[' {
+            if let Some(text_end) = find(&chars, i + 1, "](") {
+                if let Some(url_end) = find(&chars, text_end + 2, ")") {
+                    flush(&mut buf, &mut inlines);
+                    let text: String = chars[i + 1..text_end].iter().collect();
+                    inlines.push(Inline::Link {
+                        text: parse_inline(&text),
+                        url: chars[text_end + 2..url_end].iter().collect(),
+                    });
+                    i = url_end + 1;
+                    continue;
+                }
+            }
+        }
+
+        buf.push(c);
+        i += 1;
+    }
+
+    flush(&mut buf, &mut inlines);
+    inlines
+}
+
+fn inlines_to_html(inlines: &[Inline]) -> String {
+    inlines
+        .iter()
+        .map(|inline| match inline {
+            Inline::Text(text) => escape_html(text),
+            Inline::Strong(inner) => format!("<strong>{}</strong>", inlines_to_html(inner)),
+            Inline::Emphasis(inner) => format!("<em>{}</em>", inlines_to_html(inner)),
+            Inline::Code(code) => format!("<code>{}</code>", escape_html(code)),
+            Inline::Math(math) => format!(
+                "<span class=\"math inline\">\\({}\\)</span>",
+                escape_html(math)
+            ),
+            Inline::Link { text, url } if is_safe_url(url) => format!(
+                "<a href=\"{}\">{}</a>",
+                escape_html(url),
+                inlines_to_html(text)
+            ),
+            Inline::Link { text, .. } => inlines_to_html(text),
+            Inline::Media(media) => {
+                let src = media
+                    .path()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_else(|| media.file_name().to_string());
+                let src = escape_html(&src);
+                match media.kind() {
+                    MediaKind::Image => format!("<img src=\"{}\">", src),
+                    MediaKind::Audio => format!("<audio controls src=\"{}\"></audio>", src),
+                    MediaKind::Video => format!("<video controls src=\"{}\"></video>", src),
+                    MediaKind::Other => format!("<a href=\"{0}\">{0}</a>", src),
+                }
+            }
+        })
+        .collect()
+}
+
+fn inlines_to_plain(inlines: &[Inline]) -> String {
+    inlines
+        .iter()
+        .map(|inline| match inline {
+            Inline::Text(text) | Inline::Code(text) | Inline::Math(text) => text.clone(),
+            Inline::Strong(inner) | Inline::Emphasis(inner) => inlines_to_plain(inner),
+            Inline::Link { text, .. } => inlines_to_plain(text),
+            Inline::Media(_) => String::new(),
+        })
+        .collect()
+}
+
+/// Links in card text can come from someone else's collection, so only web and media links
+/// are rendered as links, not `javascript:` and the like.
+fn is_safe_url(url: &str) -> bool {
+    let url = url.trim().to_lowercase();
+    ["http://", "https://", "media:"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+}
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Inline {
+        Inline::Text(s.to_string())
+    }
+
+    #[test]
+    fn unmatched_delimiters_stay_text() {
+        for s in [
+            "**bold",
+            "*emphasis",
+            "`code",
+            "$math",
+            "[link](url",
+            "[[media:abc",
+        ] {
+            assert_eq!(parse_inline(s), vec![text(s)], "{}", s);
+        }
+    }
+
+    #[test]
+    fn prices_are_not_math() {
+        assert_eq!(parse_inline("$5 and $10"), vec![text("$5 and $10")]);
+        assert_eq!(
+            parse_inline("costs $5, $x^2$"),
+            vec![text("costs $5, "), Inline::Math("x^2".to_string())]
+        );
+    }
+
+    #[test]
+    fn underscores_inside_words_are_not_emphasis() {
+        assert_eq!(
+            parse_inline("snake_case_name"),
+            vec![text("snake_case_name")]
+        );
+        assert_eq!(
+            parse_inline("an _emphasised_ word"),
+            vec![
+                text("an "),
+                Inline::Emphasis(vec![text("emphasised")]),
+                text(" word")
+            ]
+        );
+    }
+
+    #[test]
+    fn strong_and_emphasis_nest() {
+        assert_eq!(
+            parse_inline("**bold *and* more**"),
+            vec![Inline::Strong(vec![
+                text("bold "),
+                Inline::Emphasis(vec![text("and")]),
+                text(" more")
+            ])]
+        );
+    }
+
+    #[test]
+    fn escaped_delimiters_are_text() {
+        assert_eq!(
+            parse_inline(r"\*not emphasis\*"),
+            vec![text("*not emphasis*")]
+        );
+        assert_eq!(parse_inline(r"\$5\$"), vec![text("$5$")]);
+        assert_eq!(parse_inline(r"a \\ b"), vec![text(r"a \ b")]);
+        assert_eq!(parse_inline(r"trailing \"), vec![text(r"trailing \")]);
+    }
+
+    #[test]
+    fn media_references() {
+        let media = media::references_in("[[media:3f2a.png]]").pop().unwrap();
+        assert_eq!(
+            parse_inline("look: [[media:3f2a.png]]!"),
+            vec![text("look: "), Inline::Media(media), text("!")]
+        );
+
+        // Only hash names are references, anything else could point outside the media directory.
+        assert_eq!(
+            parse_inline("[[media:../secret.png]]"),
+            vec![text("[[media:../secret.png]]")]
+        );
+    }
+
+    #[test]
+    fn only_safe_urls_are_linked() {
+        assert_eq!(
+            inlines_to_html(&parse_inline("[docs](https://example.com)")),
+            "<a href=\"https://example.com\">docs</a>"
+        );
+        assert_eq!(
+            inlines_to_html(&parse_inline("[click](javascript:alert)")),
+            "click"
+        );
+        assert_eq!(inlines_to_html(&parse_inline("[x](JavaScript:alert)")), "x");
+        assert_eq!(
+            inlines_to_html(&parse_inline("[x](data:text/html,hi)")),
+            "x"
+        );
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(
+            inlines_to_html(&parse_inline("<b> & \"x\"")),
+            "&lt;b&gt; &amp; &quot;x&quot;"
+        );
+    }
+}
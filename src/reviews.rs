@@ -6,20 +6,32 @@ use std::io::Write;
 use std::str::FromStr;
 use std::time::Duration;
 
+/// Marks a review in the details file as the first one after the card changed.
+const CONTENT_CHANGED: &str = "changed";
+
 #[derive(Ord, PartialOrd, Eq, Hash, PartialEq, Debug, Default, Clone)]
 pub struct Reviews(pub Vec<Review>);
 
 impl Reviews {
+    /// Loads the reviews along with their details, see [`Reviews::save`].
     pub fn load(id: CardId) -> Option<Self> {
         let path = paths::get_review_path().join(id.to_string());
         if path.exists() {
             let s = fs::read_to_string(path).unwrap();
-            Some(Self::from_str(&s))
+            let mut reviews = Self::from_str(&s);
+            let details = paths::get_review_details_path().join(id.to_string());
+            if let Ok(s) = fs::read_to_string(details) {
+                reviews.add_details(&s);
+            }
+            Some(reviews)
         } else {
             None
         }
     }
 
+    /// Saves the timestamp and grade of each review in the reviews file, which keeps the format
+    /// older versions can read. The time spent and whether the content changed go in a separate
+    /// details file, by timestamp.
     pub fn save(&self, id: CardId) {
         let path = get_review_path();
         fs::create_dir_all(&path).unwrap();
@@ -27,29 +39,75 @@ impl Reviews {
         let mut s = String::new();
         for r in &self.0 {
             let stamp = r.timestamp.as_secs().to_string();
-            let grade = match r.grade {
-                Recall::None => "1",
-                Recall::Late => "2",
-                Recall::Some => "3",
-                Recall::Perfect => "4",
-            };
+            let grade = r.grade.as_number();
             s.push_str(&format!("{} {}\n", stamp, grade));
         }
 
         let mut f = File::create(&path).unwrap();
         f.write_all(&mut s.as_bytes()).unwrap();
+
+        let path = paths::get_review_details_path().join(id.to_string());
+        let details = self.details();
+        if details.is_empty() {
+            if path.exists() {
+                fs::remove_file(path).unwrap();
+            }
+        } else {
+            fs::write(path, details).unwrap();
+        }
+    }
+
+    /// A line for each review with details, the timestamp, the seconds spent and the changed flag.
+    fn details(&self) -> String {
+        let mut s = String::new();
+        for r in &self.0 {
+            if r.time_spent.is_zero() && !r.content_changed {
+                continue;
+            }
+
+            let stamp = r.timestamp.as_secs();
+            let spent = r.time_spent.as_secs_f32();
+            if r.content_changed {
+                s.push_str(&format!("{} {} {}\n", stamp, spent, CONTENT_CHANGED));
+            } else {
+                s.push_str(&format!("{} {}\n", stamp, spent));
+            }
+        }
+        s
+    }
+
+    /// Adds the details to the reviews with the same timestamp, lines that can't be read are skipped.
+    fn add_details(&mut self, s: &str) {
+        for line in s.lines() {
+            let mut columns = line.split(' ');
+            let Some(timestamp) = columns.next().and_then(|secs| secs.parse().ok()) else {
+                continue;
+            };
+            let timestamp = Duration::from_secs(timestamp);
+            let time_spent = columns
+                .next()
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs_f32)
+                .unwrap_or_default();
+            let content_changed = columns.next() == Some(CONTENT_CHANGED);
+
+            for review in self.0.iter_mut().filter(|r| r.timestamp == timestamp) {
+                review.time_spent = time_spent;
+                review.content_changed = content_changed;
+            }
+        }
     }
 
     pub fn from_str(s: &str) -> Self {
         let mut reviews = vec![];
         for line in s.lines() {
-            let (timestamp, grade) = line.split_once(' ').unwrap();
-            let timestamp = Duration::from_secs(timestamp.parse().unwrap());
-            let grade = Recall::from_str(grade).unwrap();
+            let mut columns = line.split(' ');
+            let timestamp = Duration::from_secs(columns.next().unwrap().parse().unwrap());
+            let grade = Recall::from_str(columns.next().unwrap()).unwrap();
             let review = Review {
                 timestamp,
                 grade,
-                time_spent: Duration::default(),
+                ..Default::default()
             };
             reviews.push(review);
         }
@@ -79,6 +137,13 @@ impl Reviews {
         self.0.push(review);
     }
 
+    /// Adds the other reviews, keeping them sorted by time and dropping exact duplicates.
+    pub fn merge(&mut self, other: &Reviews) {
+        self.0.extend(other.0.iter().cloned());
+        self.0.sort_by_key(|r| r.timestamp);
+        self.0.dedup();
+    }
+
     pub fn lapses(&self) -> u32 {
         self.0.iter().fold(0, |lapses, review| match review.grade {
             Recall::None | Recall::Late => lapses + 1,
@@ -89,6 +154,39 @@ impl Reviews {
     pub fn time_since_last_review(&self, current_unix: Duration) -> Option<Duration> {
         self.0.last().map(|review| review.time_passed(current_unix))
     }
+
+    /// The reviews since the card's content last changed significantly.
+    /// Older reviews were of a different question, so schedulers may want to give them less weight.
+    pub fn since_content_change(&self) -> Reviews {
+        let start = self
+            .0
+            .iter()
+            .rposition(|review| review.content_changed)
+            .unwrap_or(0);
+        Self(self.0[start..].to_vec())
+    }
+
+    pub fn time_since_first_review(&self, current_unix: Duration) -> Option<Duration> {
+        self.0
+            .first()
+            .map(|review| review.time_passed(current_unix))
+    }
+
+    /// Average time spent on the reviews that have it recorded.
+    pub fn average_time_spent(&self) -> Option<Duration> {
+        let spent: Vec<Duration> = self
+            .0
+            .iter()
+            .map(|review| review.time_spent)
+            .filter(|spent| !spent.is_zero())
+            .collect();
+
+        if spent.is_empty() {
+            None
+        } else {
+            Some(spent.iter().sum::<Duration>() / spent.len() as u32)
+        }
+    }
 }
 
 #[derive(Ord, PartialOrd, Eq, PartialEq, Hash, Clone, Debug, Default)]
@@ -99,6 +197,8 @@ pub struct Review {
     pub grade: Recall,
     // How long you spent before attempting recall.
     pub time_spent: Duration,
+    // Whether the card's content changed significantly since the previous review.
+    pub content_changed: bool,
 }
 
 impl Review {
@@ -107,6 +207,7 @@ impl Review {
             timestamp: current_time(),
             grade,
             time_spent,
+            content_changed: false,
         }
     }
 
@@ -131,6 +232,16 @@ pub enum Recall {
 }
 
 impl Recall {
+    /// The number the grade is stored as, from 1 for `None` to 4 for `Perfect`.
+    pub fn as_number(&self) -> u32 {
+        match self {
+            Recall::None => 1,
+            Recall::Late => 2,
+            Recall::Some => 3,
+            Recall::Perfect => 4,
+        }
+    }
+
     pub fn get_factor(&self) -> f32 {
         match self {
             Recall::None => 0.1,
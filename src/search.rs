@@ -0,0 +1,395 @@
+use crate::answer::levenshtein;
+use crate::card::{AnyType, BackSide};
+use crate::common::{system_time_as_unix_time, CardId};
+use crate::fsck::card_files;
+use crate::media::strip_references;
+use crate::paths::get_cache_path;
+use crate::render::to_plain_text;
+use crate::Card;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Duration;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+const INDEX_FILE: &str = "search_index.json";
+
+/// Characters of context on each side of the first match in a snippet.
+const SNIPPET_CONTEXT: usize = 40;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Field {
+    Front,
+    Back,
+    Tags,
+}
+
+impl Field {
+    fn weight(&self) -> f32 {
+        match self {
+            Field::Front => 2.0,
+            Field::Back => 1.0,
+            Field::Tags => 1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Document {
+    fields: Vec<(Field, String)>,
+}
+
+impl Document {
+    fn from_card(card: &Card<AnyType>) -> Self {
+        let mut fields = vec![(Field::Front, plain(&card.print()))];
+
+        let back = match card.back_side() {
+            Some(BackSide::Text(text)) => plain(text),
+            Some(BackSide::Time(time)) => time.to_string(),
+            Some(BackSide::Card(id)) => name_of(*id),
+            Some(BackSide::List(ids)) => ids
+                .iter()
+                .map(|id| name_of(*id))
+                .collect::<Vec<_>>()
+                .join(", "),
+            None => String::new(),
+        };
+        if !back.is_empty() {
+            fields.push((Field::Back, back));
+        }
+
+        if !card.tags().is_empty() {
+            let tags = card
+                .tags()
+                .iter()
+                .map(|(tag, value)| {
+                    if value.is_empty() {
+                        tag.clone()
+                    } else {
+                        format!("{}: {}", tag, value)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            fields.push((Field::Tags, tags));
+        }
+
+        Self { fields }
+    }
+
+    /// Each distinct term with the summed weight of the fields it's in.
+    fn terms(&self) -> BTreeMap<String, f32> {
+        let mut terms = BTreeMap::new();
+        for (field, text) in &self.fields {
+            let unique: BTreeSet<String> = tokenize(text).into_iter().map(|(t, _)| t).collect();
+            for term in unique {
+                *terms.entry(term).or_default() += field.weight();
+            }
+        }
+        terms
+    }
+}
+
+fn plain(text: &str) -> String {
+    to_plain_text(&strip_references(text))
+}
+
+fn name_of(id: CardId) -> String {
+    Card::from_id(id)
+        .map(|card| plain(&card.print()))
+        .unwrap_or_default()
+}
+
+/// Lowercases and removes diacritics, so "Éire" and "eire" are the same term.
+pub fn fold(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// The folded words of the text along with their byte ranges in the original text.
+pub fn tokenize(text: &str) -> Vec<(String, Range<usize>)> {
+    let mut tokens = vec![];
+    let mut start = None;
+
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                tokens.push((fold(&text[s..i]), s..i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    tokens
+}
+
+/// Part of a field with the matching words marked by byte ranges into `text`.
+#[derive(Debug, Clone)]
+pub struct Snippet {
+    pub field: Field,
+    pub text: String,
+    pub highlights: Vec<Range<usize>>,
+}
+
+impl Snippet {
+    /// The snippet with each highlighted word wrapped in `open` and `close`, e.g. `<b>` and `</b>`.
+    pub fn highlighted(&self, open: &str, close: &str) -> String {
+        let mut s = String::new();
+        let mut last = 0;
+        for range in &self.highlights {
+            s.push_str(&self.text[last..range.start]);
+            s.push_str(open);
+            s.push_str(&self.text[range.clone()]);
+            s.push_str(close);
+            last = range.end;
+        }
+        s.push_str(&self.text[last..]);
+        s
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub id: CardId,
+    pub score: f32,
+    pub snippet: Option<Snippet>,
+}
+
+/// Full-text index over the cards, cached on disk and updated from the card files
+/// that changed since the last search.
+///
+/// Back sides that refer to other cards are indexed by those cards' names at the time,
+/// renaming a card doesn't update the cards that refer to it until they're saved again.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SearchIndex {
+    /// Modification time and card of each indexed file.
+    files: BTreeMap<PathBuf, (Duration, CardId)>,
+    docs: BTreeMap<CardId, Document>,
+    /// For each term, the cards it's in and how much weight it has in each.
+    postings: BTreeMap<String, BTreeMap<CardId, f32>>,
+}
+
+impl SearchIndex {
+    fn path() -> PathBuf {
+        get_cache_path().join(INDEX_FILE)
+    }
+
+    /// The cached index, which may be out of date until it's refreshed.
+    pub fn load() -> Self {
+        fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::write(Self::path(), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Reindexes the card files that were added, changed or removed, returns how many there were.
+    pub fn refresh(&mut self) -> usize {
+        let mut current: BTreeMap<PathBuf, Duration> = BTreeMap::new();
+        for path in card_files() {
+            if let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) {
+                current.insert(path, system_time_as_unix_time(modified));
+            }
+        }
+
+        let removed: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|path| !current.contains_key(*path))
+            .cloned()
+            .collect();
+
+        let changed: Vec<(PathBuf, Duration)> = current
+            .into_iter()
+            .filter(|(path, modified)| {
+                self.files
+                    .get(path)
+                    .map_or(true, |(indexed, _)| indexed != modified)
+            })
+            .collect();
+
+        for path in &removed {
+            if let Some((_, id)) = self.files.remove(path) {
+                self.remove(id);
+            }
+        }
+
+        for (path, modified) in &changed {
+            if let Some((_, id)) = self.files.remove(path) {
+                self.remove(id);
+            }
+
+            let Ok(id) = Card::verify_file(path) else {
+                continue;
+            };
+            if let Some(card) = Card::from_id(id) {
+                self.insert(&card);
+                self.files.insert(path.clone(), (*modified, id));
+            }
+        }
+
+        removed.len() + changed.len()
+    }
+
+    /// Every indexed card, in id order.
+    pub fn ids(&self) -> impl Iterator<Item = CardId> + '_ {
+        self.docs.keys().copied()
+    }
+
+    fn insert(&mut self, card: &Card<AnyType>) {
+        let doc = Document::from_card(card);
+        for (term, weight) in doc.terms() {
+            self.postings
+                .entry(term)
+                .or_default()
+                .insert(card.id(), weight);
+        }
+        self.docs.insert(card.id(), doc);
+    }
+
+    fn remove(&mut self, id: CardId) {
+        let Some(doc) = self.docs.remove(&id) else {
+            return;
+        };
+
+        for term in doc.terms().into_keys() {
+            if let Some(cards) = self.postings.get_mut(&term) {
+                cards.remove(&id);
+                if cards.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    /// Indexed terms matching the query term, with how good a match each is.
+    /// Exact matches beat prefix matches, which beat fuzzy matches.
+    fn matching_terms(&self, query: &str) -> Vec<(&String, f32)> {
+        let max_typos = match query.chars().count() {
+            0..=3 => 0,
+            4..=7 => 1,
+            _ => 2,
+        };
+
+        let mut terms = vec![];
+        for term in self.postings.keys() {
+            let quality = if term == query {
+                1.0
+            } else if term.starts_with(query) {
+                0.7
+            } else if max_typos > 0 && levenshtein(term, query) <= max_typos {
+                0.4
+            } else {
+                continue;
+            };
+            terms.push((term, quality));
+        }
+        terms
+    }
+
+    /// Cards matching every word of the query, best matches first.
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let query_terms: BTreeSet<String> = tokenize(query).into_iter().map(|(t, _)| t).collect();
+        if query_terms.is_empty() {
+            return vec![];
+        }
+
+        let mut scores: Option<BTreeMap<CardId, f32>> = None;
+        let mut matched: BTreeSet<&String> = BTreeSet::new();
+
+        for query_term in &query_terms {
+            let mut term_scores: BTreeMap<CardId, f32> = BTreeMap::new();
+            for (term, quality) in self.matching_terms(query_term) {
+                matched.insert(term);
+                for (id, weight) in &self.postings[term] {
+                    let score = term_scores.entry(*id).or_default();
+                    *score = score.max(quality * weight);
+                }
+            }
+
+            scores = Some(match scores {
+                None => term_scores,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(id, score)| Some((id, score + term_scores.get(&id)?)))
+                    .collect(),
+            });
+        }
+
+        let mut hits: Vec<SearchHit> = scores
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(id, score)| SearchHit {
+                id,
+                score,
+                snippet: self.docs.get(&id).and_then(|doc| snippet(doc, &matched)),
+            })
+            .collect();
+
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits
+    }
+}
+
+/// The first field with a match, cut down to the text around the first matching word.
+fn snippet(doc: &Document, matched: &BTreeSet<&String>) -> Option<Snippet> {
+    for (field, text) in &doc.fields {
+        let ranges: Vec<Range<usize>> = tokenize(text)
+            .into_iter()
+            .filter(|(term, _)| matched.contains(term))
+            .map(|(_, range)| range)
+            .collect();
+
+        let Some(first) = ranges.first() else {
+            continue;
+        };
+
+        let start = char_boundary(text, first.start.saturating_sub(SNIPPET_CONTEXT));
+        let end = char_boundary(text, (first.end + SNIPPET_CONTEXT).min(text.len()));
+
+        let highlights = ranges
+            .iter()
+            .filter(|range| range.start >= start && range.end <= end)
+            .map(|range| range.start - start..range.end - start)
+            .collect();
+
+        return Some(Snippet {
+            field: *field,
+            text: text[start..end].to_string(),
+            highlights,
+        });
+    }
+
+    None
+}
+
+fn char_boundary(text: &str, mut i: usize) -> usize {
+    while !text.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+/// Brings the cached index up to date and searches it.
+pub fn search(query: &str) -> Result<Vec<SearchHit>> {
+    let mut index = SearchIndex::load();
+    if index.refresh() > 0 {
+        index.save()?;
+    }
+    Ok(index.search(query))
+}
@@ -0,0 +1,90 @@
+use crate::common::CardId;
+use crate::filter::FilterContext;
+use crate::Card;
+use eyre::{bail, Result};
+use std::collections::BTreeMap;
+
+/// Separates the levels of a hierarchical tag, e.g. `lang::german::verbs`.
+pub const TAG_SEPARATOR: &str = "::";
+
+/// Trims each level of the tag and rejects empty levels.
+pub fn normalize_tag(tag: &str) -> Result<String> {
+    let levels: Vec<&str> = tag.split(TAG_SEPARATOR).map(str::trim).collect();
+    if levels.iter().any(|level| level.is_empty()) {
+        bail!("invalid tag: '{}'", tag);
+    }
+    Ok(levels.join(TAG_SEPARATOR))
+}
+
+/// The tag itself and all its parent tags, `a::b::c` gives `a`, `a::b` and `a::b::c`.
+pub fn tag_with_ancestors(tag: &str) -> Vec<String> {
+    let levels: Vec<&str> = tag.split(TAG_SEPARATOR).collect();
+    (1..=levels.len())
+        .map(|depth| levels[..depth].join(TAG_SEPARATOR))
+        .collect()
+}
+
+/// Whether `tag` is `parent` or nested somewhere under it.
+pub fn is_within(tag: &str, parent: &str) -> bool {
+    tag == parent
+        || tag
+            .strip_prefix(parent)
+            .is_some_and(|rest| rest.starts_with(TAG_SEPARATOR))
+}
+
+/// Number of cards per tag, cards tagged `a::b` are also counted under `a`.
+pub fn tag_index() -> BTreeMap<String, usize> {
+    let mut index: BTreeMap<String, usize> = BTreeMap::new();
+
+    for card in Card::load_all_cards() {
+        let mut card_tags: Vec<String> = card
+            .tags()
+            .keys()
+            .flat_map(|tag| tag_with_ancestors(tag))
+            .collect();
+        card_tags.sort();
+        card_tags.dedup();
+
+        for tag in card_tags {
+            *index.entry(tag).or_default() += 1;
+        }
+    }
+
+    index
+}
+
+/// All cards having the tag or one nested under it.
+pub fn cards_with_tag(tag: &str) -> Vec<CardId> {
+    Card::load_all_cards()
+        .into_iter()
+        .filter(|card| card.has_tag(tag))
+        .map(|card| card.id())
+        .collect()
+}
+
+/// Adds the tag to all cards matching the filter, returns the cards that were tagged.
+pub fn tag_filtered(filter: String, tag: &str, value: &str) -> Result<Vec<CardId>> {
+    let tag = normalize_tag(tag)?;
+    let mut tagged = vec![];
+
+    for card in FilterContext::load().matching(&filter) {
+        card.clone().add_tag(&tag, value)?;
+        tagged.push(card.id());
+    }
+
+    Ok(tagged)
+}
+
+/// Removes the tag, and tags nested under it, from all cards matching the filter.
+pub fn untag_filtered(filter: String, tag: &str) -> Vec<CardId> {
+    let mut untagged = vec![];
+
+    for card in FilterContext::load().matching(&filter) {
+        if card.has_tag(tag) {
+            card.clone().remove_tag(tag);
+            untagged.push(card.id());
+        }
+    }
+
+    untagged
+}
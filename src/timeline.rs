@@ -0,0 +1,232 @@
+use crate::card::{AnyType, BackSide};
+use crate::common::{timestamp_as_years, CardId};
+use crate::filter::FilterContext;
+use crate::graphviz::{cyan_color, rate_to_color, yellow_color};
+use crate::render::escape_html;
+use crate::Card;
+use serde::Serialize;
+use timestamped::TimeStamp;
+
+const WIDTH: f32 = 1200.;
+const MARGIN: f32 = 40.;
+const LANE_HEIGHT: f32 = 24.;
+const LABEL_WIDTH: f32 = 160.;
+
+/// A dated fact on the timeline, either an event card or a card whose backside is a time.
+#[derive(Serialize, Debug, Clone)]
+pub struct TimelineEntry {
+    pub id: CardId,
+    pub label: String,
+    pub card_type: String,
+    pub start: String,
+    pub end: Option<String>,
+    pub recall_rate: Option<f32>,
+    pub color: String,
+    #[serde(skip)]
+    start_time: TimeStamp,
+    #[serde(skip)]
+    end_time: Option<TimeStamp>,
+}
+
+impl TimelineEntry {
+    fn from_card(card: &Card<AnyType>) -> Option<Self> {
+        let (start_time, end_time) = match (card.card_type(), card.back_side()) {
+            (AnyType::Event(event), _) => (event.start_time.clone(), event.end_time.clone()),
+            (_, Some(BackSide::Time(time))) => (time.clone(), None),
+            _ => return None,
+        };
+
+        let color = match card.recall_rate() {
+            _ if !card.is_finished() => yellow_color(),
+            Some(rate) => rate_to_color(rate as f64 * 100.),
+            None => cyan_color(),
+        };
+
+        Some(Self {
+            id: card.id(),
+            label: card.print(),
+            card_type: card.card_type().type_name().to_string(),
+            start: start_time.serialize(),
+            end: end_time.as_ref().map(TimeStamp::serialize),
+            recall_rate: card.recall_rate(),
+            color,
+            start_time,
+            end_time,
+        })
+    }
+
+    fn years(&self) -> Option<(f32, f32)> {
+        let start = timestamp_as_years(&self.start_time)?;
+        let end = self
+            .end_time
+            .as_ref()
+            .and_then(timestamp_as_years)
+            .unwrap_or(start);
+        Some((start, end.max(start)))
+    }
+}
+
+/// Loads all dated cards matching the filter, in chronological order.
+pub fn load_entries(filter: Option<String>) -> Vec<TimelineEntry> {
+    let ctx = FilterContext::load();
+    let mut entries: Vec<TimelineEntry> = ctx
+        .cards()
+        .filter(|card| match &filter {
+            Some(filter) => ctx.matches(card, filter),
+            None => true,
+        })
+        .filter_map(TimelineEntry::from_card)
+        .collect();
+
+    entries.sort_by(|a, b| {
+        a.start_time
+            .cmp(&b.start_time)
+            .then_with(|| a.end_time.cmp(&b.end_time))
+    });
+    entries
+}
+
+pub fn export_json(filter: Option<String>) -> String {
+    serde_json::to_string_pretty(&load_entries(filter)).unwrap()
+}
+
+pub fn export_html(filter: Option<String>) -> String {
+    let svg = export_svg(filter);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>speki timeline</title>\n\
+         <style>body {{ font-family: sans-serif; margin: 0; }} svg text {{ font-size: 12px; }}</style>\n\
+         </head>\n<body>\n{}</body>\n</html>\n",
+        svg
+    )
+}
+
+pub fn export_svg(filter: Option<String>) -> String {
+    let entries: Vec<(TimelineEntry, (f32, f32))> = load_entries(filter)
+        .into_iter()
+        .filter_map(|entry| {
+            let years = entry.years()?;
+            Some((entry, years))
+        })
+        .collect();
+
+    let min = entries
+        .iter()
+        .map(|(_, (start, _))| *start)
+        .fold(f32::MAX, f32::min);
+    let max = entries
+        .iter()
+        .map(|(_, (_, end))| *end)
+        .fold(f32::MIN, f32::max);
+    let (min, max) = if entries.is_empty() {
+        (0., 1.)
+    } else if max - min < 1. {
+        (min - 0.5, max + 0.5)
+    } else {
+        (min, max)
+    };
+
+    let x = |years: f32| MARGIN + (years - min) / (max - min) * (WIDTH - 2. * MARGIN - LABEL_WIDTH);
+
+    // Greedily put each entry in the first lane where it doesn't collide with the previous one.
+    let mut lane_ends: Vec<f32> = vec![];
+    let mut body = String::new();
+
+    for (entry, (start, end)) in &entries {
+        let x_start = x(*start);
+        let x_end = x(*end).max(x_start + 4.);
+
+        let lane = match lane_ends.iter().position(|lane_end| *lane_end < x_start) {
+            Some(lane) => lane,
+            None => {
+                lane_ends.push(0.);
+                lane_ends.len() - 1
+            }
+        };
+        lane_ends[lane] = x_end + LABEL_WIDTH;
+
+        let y = MARGIN + lane as f32 * LANE_HEIGHT;
+        let title = format!(
+            "{} ({}{})",
+            entry.label,
+            entry.start,
+            entry
+                .end
+                .as_ref()
+                .map(|end| format!(" - {}", end))
+                .unwrap_or_default()
+        );
+
+        if entry.end.is_some() {
+            body.push_str(&format!(
+                "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"12\" rx=\"3\" fill=\"{}\"><title>{}</title></rect>\n",
+                x_start,
+                y,
+                x_end - x_start,
+                entry.color,
+                escape_html(&title)
+            ));
+        } else {
+            body.push_str(&format!(
+                "  <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"6\" fill=\"{}\"><title>{}</title></circle>\n",
+                x_start,
+                y + 6.,
+                entry.color,
+                escape_html(&title)
+            ));
+        }
+
+        body.push_str(&format!(
+            "  <text x=\"{:.1}\" y=\"{:.1}\">{}</text>\n",
+            x_end + 6.,
+            y + 10.,
+            escape_html(&entry.label)
+        ));
+    }
+
+    let axis_y = MARGIN + lane_ends.len() as f32 * LANE_HEIGHT + 10.;
+    let height = axis_y + MARGIN;
+
+    let mut axis = format!(
+        "  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"black\"/>\n",
+        x(min),
+        axis_y,
+        x(max),
+        axis_y
+    );
+
+    for tick in ticks(min, max) {
+        axis.push_str(&format!(
+            "  <line x1=\"{0:.1}\" y1=\"{1:.1}\" x2=\"{0:.1}\" y2=\"{2:.1}\" stroke=\"black\"/>\n  <text x=\"{0:.1}\" y=\"{3:.1}\" text-anchor=\"middle\">{4}</text>\n",
+            x(tick),
+            axis_y,
+            axis_y + 5.,
+            axis_y + 18.,
+            tick
+        ));
+    }
+
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n{}{}</svg>\n",
+        WIDTH, height, body, axis
+    )
+}
+
+/// Round years to put on the axis, about ten of them.
+fn ticks(min: f32, max: f32) -> Vec<f32> {
+    let raw_step = (max - min) / 10.;
+    let magnitude = 10f32.powf(raw_step.log10().floor());
+    let step = [1., 2., 5., 10.]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|step| *step >= raw_step)
+        .unwrap_or(raw_step)
+        .max(1.);
+
+    let mut ticks = vec![];
+    let mut tick = (min / step).ceil() * step;
+    while tick <= max {
+        ticks.push(tick);
+        tick += step;
+    }
+    ticks
+}
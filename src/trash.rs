@@ -0,0 +1,350 @@
+use crate::attribute::{Attribute, AttributeId};
+use crate::card::AnyType;
+use crate::common::{current_time, CardId};
+use crate::graph::{cycle_error, DependencyGraph};
+use crate::paths::{get_review_details_path, get_review_path, get_revisions_path, get_trash_path};
+use crate::Card;
+use eyre::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const CARD_FILE: &str = "card.toml";
+const REVIEWS_FILE: &str = "reviews";
+const REVIEW_DETAILS_FILE: &str = "review_details";
+const ENTRY_FILE: &str = "entry.toml";
+const ATTRIBUTES_DIR: &str = "attributes";
+
+/// What to do with the cards that depend on a card being deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteMode {
+    /// Don't delete the card if anything depends on it.
+    Refuse,
+    /// Delete the dependents too, and their dependents, and so on.
+    Cascade,
+    /// Point the dependents at this card instead.
+    Relink(CardId),
+}
+
+/// A deleted card, kept in the trash directory along with its reviews until the trash is emptied.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub id: CardId,
+    pub front: String,
+    /// Where the card file was before it was deleted, it's restored there.
+    pub original_path: PathBuf,
+    /// Unix time in seconds.
+    pub deleted_at: u64,
+    /// Attributes of the card that were deleted along with it, they're restored with it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<TrashedAttribute>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashedAttribute {
+    pub id: AttributeId,
+    pub original_path: PathBuf,
+}
+
+impl TrashEntry {
+    fn dir(id: CardId) -> PathBuf {
+        get_trash_path().join(id.to_string())
+    }
+
+    fn load(dir: &Path) -> Option<Self> {
+        let s = fs::read_to_string(dir.join(ENTRY_FILE)).ok()?;
+        toml::from_str(&s).ok()
+    }
+
+    fn save(&self) -> Result<()> {
+        let dir = Self::dir(self.id);
+        fs::write(dir.join(ENTRY_FILE), toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn attribute_file(&self, id: AttributeId) -> PathBuf {
+        Self::dir(self.id)
+            .join(ATTRIBUTES_DIR)
+            .join(format!("{}.toml", id.into_inner()))
+    }
+}
+
+/// The attribute of a trashed card file, if it's an attribute card.
+#[derive(Deserialize)]
+struct TrashedCard {
+    attribute: Option<AttributeId>,
+}
+
+/// Moves the card to the trash, returns every card that was deleted.
+pub fn delete(id: CardId, mode: DeleteMode) -> Result<Vec<CardId>> {
+    let Some(card) = Card::from_id(id) else {
+        bail!("no card with id {}", id);
+    };
+
+    let graph = DependencyGraph::load();
+    let dependents = graph.dependents(id);
+    let attributes: Vec<Attribute> = Attribute::load_all()
+        .into_iter()
+        .filter(|attr| attr.class == id)
+        .collect();
+
+    match mode {
+        DeleteMode::Refuse => {
+            if !dependents.is_empty() || !attributes.is_empty() {
+                let names: Vec<String> = dependents
+                    .iter()
+                    .filter_map(|id| Card::from_id(*id))
+                    .map(|card| card.print())
+                    .chain(attributes.iter().map(|attr| attr.pattern.clone()))
+                    .collect();
+                bail!(
+                    "can't delete '{}', these depend on it: {}",
+                    card.print(),
+                    names.join(", ")
+                );
+            }
+            move_to_trash(card)?;
+            Ok(vec![id])
+        }
+        DeleteMode::Cascade => {
+            let mut deleted = vec![id];
+            deleted.extend(graph.all_dependents(id));
+
+            // Attribute cards of instances outside the deleted classes would be left without their attribute.
+            let all_attributes = Attribute::load_all();
+            let mut i = 0;
+            while i < deleted.len() {
+                let class = deleted[i];
+                for attr in all_attributes.iter().filter(|attr| attr.class == class) {
+                    for card in attr.cards() {
+                        for card in std::iter::once(card).chain(graph.all_dependents(card)) {
+                            if !deleted.contains(&card) {
+                                deleted.push(card);
+                            }
+                        }
+                    }
+                }
+                i += 1;
+            }
+
+            trash_cards(&deleted)?;
+            Ok(deleted)
+        }
+        DeleteMode::Relink(new) => {
+            if new == id {
+                bail!("can't relink a card to itself");
+            }
+            match Card::from_id(new) {
+                None => bail!("no card with id {}", new),
+                Some(card) if !attributes.is_empty() && !card.is_class() => {
+                    bail!(
+                        "'{}' has attributes, it can only be relinked to a class",
+                        card.print()
+                    )
+                }
+                Some(_) => {}
+            }
+
+            for dependent in &dependents {
+                if let Some(err) = cycle_error(*dependent, new) {
+                    bail!(err);
+                }
+                if let Some(card) = Card::from_id(*dependent) {
+                    if let Err(err) = card.verify_replace_reference(id, new) {
+                        bail!("can't relink '{}': {}", card.print(), err);
+                    }
+                }
+            }
+
+            for dependent in dependents {
+                Card::from_id(dependent).unwrap().replace_reference(id, new);
+            }
+
+            for mut attr in attributes {
+                attr.class = new;
+                attr.update()?;
+            }
+
+            move_to_trash(card)?;
+            Ok(vec![id])
+        }
+    }
+}
+
+/// Moves the cards to the trash along with the attributes of the classes among them.
+pub(crate) fn trash_cards(ids: &[CardId]) -> Result<()> {
+    // Attribute cards go first, their names are made from their instances.
+    let (attribute_cards, others): (Vec<Card<AnyType>>, Vec<Card<AnyType>>) = ids
+        .iter()
+        .filter_map(|id| Card::from_id(*id))
+        .partition(|card| matches!(card.card_type(), AnyType::Attribute(_)));
+    for card in attribute_cards.into_iter().chain(others) {
+        move_to_trash(card)?;
+    }
+
+    // Attributes go last, as attribute cards can't be loaded without them.
+    for class in ids {
+        let attributes = Attribute::load_from_class_only(*class);
+        if !attributes.is_empty() {
+            move_attributes_to_trash(*class, attributes)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn move_to_trash(card: Card<AnyType>) -> Result<()> {
+    let dir = TrashEntry::dir(card.id());
+    fs::create_dir_all(&dir)?;
+
+    let entry = TrashEntry {
+        id: card.id(),
+        front: card.print(),
+        original_path: card.as_path(),
+        deleted_at: current_time().as_secs(),
+        attributes: vec![],
+    };
+
+    fs::rename(card.as_path(), dir.join(CARD_FILE))?;
+
+    let reviews = get_review_path().join(card.id().to_string());
+    if reviews.exists() {
+        fs::rename(reviews, dir.join(REVIEWS_FILE))?;
+    }
+
+    let details = get_review_details_path().join(card.id().to_string());
+    if details.exists() {
+        fs::rename(details, dir.join(REVIEW_DETAILS_FILE))?;
+    }
+
+    entry.save()
+}
+
+/// Moves the attributes of a card that's already in the trash into its trash entry,
+/// so they're restored along with it. Their attribute cards should be in the trash already.
+pub(crate) fn move_attributes_to_trash(id: CardId, attributes: Vec<Attribute>) -> Result<()> {
+    let Some(mut entry) = TrashEntry::load(&TrashEntry::dir(id)) else {
+        bail!("card {} is not in the trash", id);
+    };
+
+    for attr in attributes {
+        let path = entry.attribute_file(attr.id);
+        fs::create_dir_all(path.parent().unwrap())?;
+        let original_path = Attribute::move_out(attr.id, &path)?;
+        entry.attributes.push(TrashedAttribute {
+            id: attr.id,
+            original_path,
+        });
+        entry.save()?;
+    }
+
+    Ok(())
+}
+
+/// Moves the card and its reviews back to where they were, along with the attributes
+/// that were deleted with it.
+///
+/// Attribute cards whose attribute is still in the trash can't be restored before it.
+pub fn restore(id: CardId) -> Result<()> {
+    let dir = TrashEntry::dir(id);
+    let Some(entry) = TrashEntry::load(&dir) else {
+        bail!("card {} is not in the trash", id);
+    };
+
+    if Card::from_id(id).is_some() {
+        bail!("a card with id {} already exists", id);
+    }
+
+    let trashed: TrashedCard = toml::from_str(&fs::read_to_string(dir.join(CARD_FILE))?)?;
+    if let Some(attribute) = trashed.attribute {
+        if Attribute::load(attribute).is_none() {
+            match list()
+                .into_iter()
+                .find(|other| other.attributes.iter().any(|attr| attr.id == attribute))
+            {
+                Some(other) => bail!(
+                    "the attribute of '{}' was deleted with '{}', restore that first",
+                    entry.front,
+                    other.front
+                ),
+                None => bail!("the attribute of '{}' no longer exists", entry.front),
+            }
+        }
+    }
+
+    for attr in &entry.attributes {
+        Attribute::move_back(&entry.attribute_file(attr.id), &attr.original_path)?;
+    }
+
+    if let Some(parent) = entry.original_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(dir.join(CARD_FILE), &entry.original_path)?;
+
+    let reviews = dir.join(REVIEWS_FILE);
+    if reviews.exists() {
+        fs::rename(reviews, get_review_path().join(id.to_string()))?;
+    }
+
+    let details = dir.join(REVIEW_DETAILS_FILE);
+    if details.exists() {
+        fs::rename(details, get_review_details_path().join(id.to_string()))?;
+    }
+
+    fs::remove_dir_all(dir)?;
+    Card::invalidate_attribute_cards();
+    Ok(())
+}
+
+/// Deleted cards, most recently deleted first.
+pub fn list() -> Vec<TrashEntry> {
+    let mut entries: Vec<TrashEntry> = crate::collections::get_dirs(&get_trash_path())
+        .into_iter()
+        .filter_map(|dir| TrashEntry::load(&dir))
+        .collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.deleted_at));
+    entries
+}
+
+/// The card files in the trash.
+pub(crate) fn card_files() -> Vec<PathBuf> {
+    crate::collections::get_dirs(&get_trash_path())
+        .into_iter()
+        .map(|dir| dir.join(CARD_FILE))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Permanently deletes the card from the trash.
+pub fn purge(id: CardId) -> Result<()> {
+    let dir = TrashEntry::dir(id);
+    if !dir.exists() {
+        bail!("card {} is not in the trash", id);
+    }
+    fs::remove_dir_all(dir)?;
+    remove_revisions(id)?;
+    Ok(())
+}
+
+fn remove_revisions(id: CardId) -> Result<()> {
+    let path = get_revisions_path().join(id.to_string());
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Permanently deletes everything in the trash, returns how many cards were removed.
+pub fn empty() -> Result<usize> {
+    let mut count = 0;
+    for dir in crate::collections::get_dirs(&get_trash_path()) {
+        let name = dir.file_name().unwrap().to_string_lossy().to_string();
+        if let Ok(id) = CardId::from_str(&name) {
+            fs::remove_dir_all(dir)?;
+            remove_revisions(id)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}